@@ -1 +1 @@
-RUSTFLAGS="-C target-cpu=native" cargo bench
\ No newline at end of file
+cargo bench
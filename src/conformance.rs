@@ -0,0 +1,548 @@
+//! Renders the same viewports through every available backend and checks the
+//! iteration maps against `single::escapes`.
+//!
+//! Tolerance: backends that iterate every pixel in f64 must match the reference
+//! exactly, pixel for pixel. Backends that iterate in f32 drift away from the f64 orbit
+//! near the boundary of the set, double-double and perturbation round differently from
+//! plain f64, and backends that fill in pixels (`exact: false`) can miss details between
+//! their samples, so up to `MISMATCH` of their pixels may differ.
+//! Backends that are not available on this machine (no OpenCL ICD) are skipped.
+//!
+//! Smooth counts follow the same rule, except that a pixel of those backends only counts
+//! as different when it is more than `SMOOTH_DELTA` away from the reference.
+
+use crate::dd_simd::DDSIMDMandelbrot;
+use crate::dd_single::DDSingleMandelbrot;
+use crate::error::RenderError;
+use crate::fixed::Fixed;
+use crate::mariani::MarianiSilver;
+use crate::multi::MultiMandelbrot;
+use crate::opencl::OCLMandelbrot;
+use crate::perturbation::PerturbationMandelbrot;
+use crate::registry::{Capabilities, Precision, Registry};
+use crate::renderer::MandelbrotRenderer;
+use crate::renderer::{RenderParams, Counts};
+use crate::schedule::{Pool, Pooled};
+use crate::shift::shift;
+use crate::simd::{Isa, SIMDMandelbrot};
+use crate::single::SingleMandelbrot;
+use crate::single::{escapes, escapes_smooth, escapes_fast, escapes_smooth_fast, in_main_bulbs};
+use crate::symmetry::Mirrored;
+use crate::viewport::{Viewport, Tile};
+use num::Complex;
+
+const LIMIT: usize = 200;
+const MISMATCH: f64 = 0.02;
+const SMOOTH_DELTA: f32 = 0.05;
+
+/// A backend of the default registry that can be built on this machine.
+struct Backend {
+    name: &'static str,
+    capabilities: Capabilities,
+    renderer: Box<dyn MandelbrotRenderer>,
+}
+
+impl Backend {
+    /// Whether the backend has to reproduce the reference pixel for pixel.
+    fn is_exact(&self) -> bool {
+        self.capabilities.exact && self.capabilities.precision == Precision::F64
+    }
+}
+
+/// Every backend of `Registry::default()` but those this machine lacks (no OpenCL ICD).
+fn available_renderers() -> Vec<Backend> {
+    Registry::default().entries()
+        .iter()
+        .filter_map(|entry| match entry.create() {
+            Ok(renderer) => Some(Backend { name: entry.name, capabilities: entry.capabilities.clone(), renderer }),
+            Err(e) => {
+                println!("skipping {}: {}", entry.name, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// The available backends that iterate every pixel; a filling backend may fill a tile
+/// differently from the whole frame.
+fn exact_renderers() -> Vec<Backend> {
+    available_renderers().into_iter().filter(|backend| backend.capabilities.exact).collect()
+}
+
+fn viewports() -> Vec<Viewport> {
+    let mut rotated = Viewport::new(Complex::new(-0.75, 0.1), 0.5, (64, 48));
+    rotated.rotation = 0.3;
+    vec![
+        Viewport::new(Complex::new(-0.5, 0.0), 3.0, (96, 64)),
+        Viewport::new(Complex::new(-0.7436, 0.1318), 0.01, (64, 64)),
+        Viewport::new(Complex::new(-1.2477421233060082, 0.03592797277347884), 0.05, (40, 24)),
+        Viewport::new(Complex::new(0.3, -0.5), 0.2, (8, 1)),
+        rotated,
+    ]
+}
+
+fn reference<T, F: Fn(Complex<f64>) -> T>(viewport: &Viewport, f: F) -> Vec<T> {
+    let map = viewport.pixel_map();
+    let (width, height) = viewport.dims();
+    (0..width * height)
+        .map(|idx| f(map.at((idx % width) as f64, (idx / width) as f64)))
+        .collect()
+}
+
+fn check<T, F>(backend: &Backend, viewport: &Viewport, got: &[T], expected: &[T], differ: F)
+    where F: Fn(&T, &T) -> bool
+{
+    let name = backend.name;
+    assert_eq!(got.len(), expected.len(), "{} at {:?}", name, viewport);
+    let mismatched = got.iter().zip(expected).filter(|(a, b)| differ(a, b)).count();
+    if backend.is_exact() {
+        assert_eq!(mismatched, 0, "{} at {:?}", name, viewport);
+    } else {
+        let allowed = (MISMATCH * expected.len() as f64).ceil() as usize;
+        assert!(
+            mismatched <= allowed,
+            "{} at {:?}: {} of {} pixels differ",
+            name, viewport, mismatched, expected.len()
+        );
+    }
+}
+
+#[test]
+fn escape_count_contract() {
+    // |z| never exceeds 2
+    assert_eq!(escapes(Complex::new(0.0, 0.0), 50), 50);
+    assert_eq!(escapes(Complex::new(-2.0, 0.0), 50), 50);
+    // 1, 2, 5: |z| = 2 is still inside, the third iteration escapes
+    assert_eq!(escapes(Complex::new(1.0, 0.0), 50), 2);
+    assert_eq!(escapes(Complex::new(1.0, 0.0), 3), 2);
+    assert_eq!(escapes(Complex::new(1.0, 0.0), 2), 2);
+    // outside the bailout after the first iteration
+    assert_eq!(escapes(Complex::new(3.0, 0.0), 50), 0);
+}
+
+#[test]
+fn backends_match_reference() {
+    for backend in available_renderers() {
+        for viewport in viewports() {
+            let precision = backend.capabilities.precision;
+
+            let expected = reference(&viewport, |c| escapes(c, LIMIT as u64) as u32);
+            let counts = backend.renderer.render(&viewport, LIMIT).unwrap();
+            check(&backend, &viewport, &counts, &expected, |a, b| a != b);
+
+            let expected = reference(&viewport, |c| escapes_smooth(c, LIMIT as u64));
+            let smooth = backend.renderer.render_smooth(&viewport, LIMIT).unwrap();
+            let delta = match precision {
+                Precision::F64 => 0.0,
+                _ => SMOOTH_DELTA,
+            };
+            check(&backend, &viewport, &smooth, &expected, |a, b| (a - b).abs() > delta);
+        }
+    }
+}
+
+#[test]
+fn backends_agree_with_each_other() {
+    let backends: Vec<_> = available_renderers().into_iter().filter(Backend::is_exact).collect();
+    for viewport in viewports() {
+        let first = &backends[0];
+        let expected = first.renderer.render(&viewport, LIMIT).unwrap();
+        for backend in &backends[1..] {
+            assert_eq!(
+                backend.renderer.render(&viewport, LIMIT).unwrap(), expected,
+                "{} and {} disagree at {:?}", backend.name, first.name, viewport
+            );
+        }
+    }
+}
+
+#[test]
+fn smooth_interior_is_limit() {
+    // the main cardioid never escapes, everything past |c| = 2 escapes at once
+    assert_eq!(escapes_smooth(Complex::new(-0.1, 0.1), 50), 50.0);
+    let outside = escapes_smooth(Complex::new(3.0, 0.0), 50);
+    assert!(outside >= 0.0 && outside < 1.0, "{}", outside);
+}
+
+#[test]
+fn interior_shortcuts_match_reference() {
+    assert!(in_main_bulbs(Complex::new(-0.1, 0.1)));
+    assert!(in_main_bulbs(Complex::new(-1.0, 0.1)));
+    assert!(!in_main_bulbs(Complex::new(0.3, 0.0)));
+    assert!(!in_main_bulbs(Complex::new(-0.75, 0.2)));
+
+    // the whole set at a limit high enough for most cycles to be found
+    let viewport = Viewport::new(Complex::new(-0.75, 0.0), 2.8, (160, 120));
+    let limit = 1000;
+    let expected = reference(&viewport, |c| escapes(c, limit));
+    assert_eq!(reference(&viewport, |c| escapes_fast(c, limit)), expected);
+    let expected = reference(&viewport, |c| escapes_smooth(c, limit));
+    assert_eq!(reference(&viewport, |c| escapes_smooth_fast(c, limit)), expected);
+}
+
+#[test]
+fn progressive_matches_render() {
+    let viewport = Viewport::new(Complex::new(-0.5, 0.0), 3.0, (64, 37));
+    let (width, height) = viewport.dims();
+    for Backend { name, renderer, .. } in exact_renderers() {
+        let mut passes = Vec::new();
+        let counts = renderer.render_progressive(&viewport, &RenderParams::new(LIMIT), &mut |frame: &[u32]| passes.push(frame.to_vec())).unwrap();
+        assert_eq!(counts, renderer.render(&viewport, LIMIT).unwrap(), "{}", name);
+        assert_eq!(passes.len(), 3, "{}", name);
+        assert_eq!(passes[2], counts, "{}", name);
+        // the first pass only has every 4th pixel, blocks repeat their top-left sample
+        for y in 0..height {
+            for x in 0..width {
+                assert_eq!(passes[0][y * width + x], counts[(y - y % 4) * width + x - x % 4], "{}", name);
+            }
+        }
+    }
+}
+
+#[test]
+fn shift_matches_render() {
+    // pixels of 1/16: panned viewports sample exactly the same points
+    let viewport = Viewport::new(Complex::new(-0.5, 0.0), 4.0, (64, 40));
+    let params = RenderParams::new(LIMIT);
+    for backend in exact_renderers() {
+        let renderer = &backend.renderer;
+        let previous = renderer.render(&viewport, LIMIT).unwrap();
+        for &(dx, dy) in &[(3, 0), (-13, 0), (0, 7), (0, -1), (21, -9), (-64, 2), (5, 40)] {
+            let mut panned = viewport;
+            panned.pan(dx as f64, dy as f64);
+            assert_eq!(panned.pixel_offset(&viewport), Some((dx, dy)));
+            let counts = shift(&panned, &previous, (dx, dy), |tile, out: &mut [u32]| renderer.render_tile(&panned, tile, &params, Counts::U32(out))).unwrap();
+            // perturbation follows a new reference orbit after the pan
+            let expected = renderer.render(&panned, LIMIT).unwrap();
+            check(&backend, &panned, &counts, &expected, |a, b| a != b);
+        }
+    }
+}
+
+#[test]
+fn rejects_tile_outside_viewport() {
+    let viewport = Viewport::new(Complex::new(0.0, 0.0), 1.0, (16, 16));
+    let tile = Tile { x: 8, y: 0, width: 16, height: 4, step: 1 };
+    for Backend { name, renderer, .. } in available_renderers() {
+        let mut out = vec![0; tile.len()];
+        assert!(renderer.render_tile(&viewport, &tile, &RenderParams::new(LIMIT), Counts::U32(&mut out)).is_err(), "{}", name);
+    }
+}
+
+#[test]
+fn tiles_match_render() {
+    let viewport = Viewport::new(Complex::new(-0.7436, 0.1318), 0.01, (64, 48));
+    let params = RenderParams::new(LIMIT);
+    let tiles = [
+        Tile { x: 0, y: 0, width: 64, height: 1, step: 1 },
+        Tile { x: 16, y: 5, width: 32, height: 20, step: 1 },
+        Tile { x: 56, y: 47, width: 8, height: 1, step: 1 },
+        Tile { x: 8, y: 3, width: 16, height: 9, step: 3 },
+        // widths that leave a partial block of SIMD lanes
+        Tile { x: 3, y: 7, width: 13, height: 5, step: 1 },
+        Tile { x: 1, y: 2, width: 21, height: 4, step: 3 },
+    ];
+    for Backend { name, renderer, .. } in exact_renderers() {
+        let frame = renderer.render(&viewport, LIMIT).unwrap();
+        for tile in tiles.iter() {
+            let mut out = vec![0; tile.len()];
+            renderer.render_tile(&viewport, tile, &params, Counts::U32(&mut out)).unwrap();
+            let expected: Vec<u32> = (0..tile.len())
+                .map(|idx| {
+                    let (x, y) = tile.pixel(idx % tile.width, idx / tile.width);
+                    frame[y * viewport.width + x]
+                })
+                .collect();
+            assert_eq!(out, expected, "{} {:?}", name, tile);
+        }
+        // one value per sample, no more and no less
+        let mut short = vec![0; tiles[1].len() - 1];
+        let result = renderer.render_tile(&viewport, &tiles[1], &params, Counts::U32(&mut short));
+        assert!(matches!(result, Err(RenderError::BufferSize { .. })), "{}", name);
+    }
+}
+
+#[test]
+fn count_types_agree() {
+    let viewport = Viewport::new(Complex::new(-0.5, 0.0), 3.0, (64, 40));
+    let tile = Tile::full(&viewport);
+    let params = RenderParams::new(LIMIT);
+    for Backend { name, renderer, .. } in available_renderers() {
+        let expected = renderer.render(&viewport, LIMIT).unwrap();
+        let mut short = vec![0u16; tile.len()];
+        renderer.render_tile(&viewport, &tile, &params, Counts::U16(&mut short)).unwrap();
+        assert!(short.iter().zip(&expected).all(|(&a, &b)| a as u32 == b), "{}", name);
+        let mut float = vec![0f32; tile.len()];
+        renderer.render_tile(&viewport, &tile, &params, Counts::F32(&mut float)).unwrap();
+        assert!(float.iter().zip(&expected).all(|(&a, &b)| a == b as f32), "{}", name);
+
+        // u16 counts cannot hold the limit
+        let result = renderer.render_tile(&viewport, &tile, &RenderParams::new(70_000), Counts::U16(&mut short));
+        assert!(matches!(result, Err(RenderError::LimitTooLarge { .. })), "{}", name);
+    }
+}
+
+/// xorshift64, enough to spread the sizes and places of the property tests.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    /// Uniform in [0, 1).
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[test]
+fn simd_matches_single_for_odd_sizes() {
+    let registry = Registry::default();
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for &(simd, single) in &[("simd", "single"), ("dd-simd", "dd-single")] {
+        let (simd, single) = (registry.create(simd).unwrap(), registry.create(single).unwrap());
+        for _ in 0..40 {
+            let width = 2 * rng.below(20) as usize + 1;
+            let height = rng.below(9) as usize + 1;
+            let center = Complex::new(2.5 * rng.unit() - 2.0, 2.0 * rng.unit() - 1.0);
+            let mut viewport = Viewport::new(center, 10f64.powf(-4.0 * rng.unit()), (width, height));
+            viewport.rotation = rng.unit();
+            assert_eq!(simd.render(&viewport, LIMIT).unwrap(), single.render(&viewport, LIMIT).unwrap(), "{:?}", viewport);
+            assert_eq!(simd.render_smooth(&viewport, LIMIT).unwrap(), single.render_smooth(&viewport, LIMIT).unwrap(), "{:?}", viewport);
+        }
+    }
+}
+
+#[test]
+fn every_isa_matches_single() {
+    let single = SingleMandelbrot::new().unwrap();
+    let supported: Vec<_> = Isa::ALL.iter().filter_map(|&isa| SIMDMandelbrot::with_isa(isa, false)).collect();
+    assert!(supported.iter().any(|renderer| renderer.isa() == Isa::detect()));
+    for viewport in viewports() {
+        let expected = single.render(&viewport, LIMIT).unwrap();
+        let expected_smooth = single.render_smooth(&viewport, LIMIT).unwrap();
+        for renderer in &supported {
+            assert_eq!(renderer.render(&viewport, LIMIT).unwrap(), expected, "{:?} at {:?}", renderer.isa(), viewport);
+            assert_eq!(renderer.render_smooth(&viewport, LIMIT).unwrap(), expected_smooth, "{:?} at {:?}", renderer.isa(), viewport);
+        }
+    }
+    // narrow lanes only for views f32 resolves
+    let narrow = SIMDMandelbrot::narrow();
+    let deep = Viewport::new(Complex::new(-0.7436, 0.1318), 1e-9, (8, 8));
+    assert_eq!(narrow.precision_for(&deep, LIMIT), Precision::F64);
+    // and for counts f32 holds, the backend reports what it picks
+    let shallow = Viewport::new(Complex::new(-0.5, 0.0), 3.0, (8, 8));
+    assert_eq!(narrow.precision(&shallow, 1 << 25), Some(Precision::F64));
+    assert_eq!(narrow.precision(&shallow, LIMIT), Some(narrow.precision_for(&shallow, LIMIT)));
+    assert_eq!(narrow.render(&deep, LIMIT).unwrap(), single.render(&deep, LIMIT).unwrap());
+}
+
+#[test]
+fn own_pools_match_single() {
+    let single = SingleMandelbrot::new().unwrap();
+    let pool = |threads| Pool::with_threads(threads).unwrap();
+    let renderers = [
+        ("multi, 1 thread", Box::new(Pooled::wrap(MultiMandelbrot::new().unwrap(), pool(1))) as Box<dyn MandelbrotRenderer>),
+        ("multi, 3 threads", Box::new(Pooled::wrap(MultiMandelbrot::new().unwrap(), pool(3)))),
+        ("simd, 2 threads", Box::new(Pooled::wrap(SIMDMandelbrot::new().unwrap(), pool(2)))),
+        ("registry simd, 2 threads", Registry::with_threads(2).unwrap().create("simd").unwrap()),
+    ];
+    for viewport in viewports() {
+        let expected = single.render(&viewport, LIMIT).unwrap();
+        let expected_smooth = single.render_smooth(&viewport, LIMIT).unwrap();
+        for (name, renderer) in &renderers {
+            assert_eq!(renderer.render(&viewport, LIMIT).unwrap(), expected, "{} at {:?}", name, viewport);
+            assert_eq!(renderer.render_smooth(&viewport, LIMIT).unwrap(), expected_smooth, "{} at {:?}", name, viewport);
+        }
+    }
+}
+
+#[test]
+fn rejects_empty_viewport() {
+    let viewport = Viewport::new(Complex::new(0.0, 0.0), 1.0, (0, 16));
+    for Backend { name, renderer, .. } in available_renderers() {
+        assert!(renderer.render(&viewport, LIMIT).is_err(), "{}", name);
+    }
+}
+
+#[test]
+fn cancelled_render_stops() {
+    let viewport = Viewport::new(Complex::new(-0.5, 0.0), 3.0, (64, 64));
+    let tile = Tile::full(&viewport);
+    let params = RenderParams::new(LIMIT);
+    params.cancel.cancel();
+    for Backend { name, renderer, .. } in available_renderers() {
+        let counts = renderer.render_tile(&viewport, &tile, &params, Counts::U32(&mut vec![0; tile.len()]));
+        assert!(matches!(counts, Err(RenderError::Cancelled)), "{}", name);
+        let smooth = renderer.render_tile_smooth(&viewport, &tile, &params, &mut vec![0.0; tile.len()]);
+        assert!(matches!(smooth, Err(RenderError::Cancelled)), "{}", name);
+        let mut passes = 0;
+        let progressive = renderer.render_progressive(&viewport, &params, &mut |_: &[u32]| passes += 1);
+        assert!(matches!(progressive, Err(RenderError::Cancelled)), "{}", name);
+        assert_eq!(passes, 0, "{}", name);
+    }
+}
+
+/// Escape count of `c` iterated in [`Fixed`] point, the reference for deep zooms.
+fn fixed_escapes(c: Complex<Fixed>, limit: u64) -> u64 {
+    let mut z = Complex { re: Fixed::ZERO, im: Fixed::ZERO };
+    for i in 0..limit {
+        let product = z.re * z.im;
+        z = Complex { re: z.re.square() - z.im.square() + c.re, im: product + product + c.im };
+        if z.re.to_f64().powi(2) + z.im.to_f64().powi(2) > 4.0 {
+            return i;
+        }
+    }
+    limit
+}
+
+/// `fixed_escapes` of every pixel of `viewport`, from its precise center.
+fn fixed_reference(viewport: &Viewport, limit: u64) -> Vec<u32> {
+    let map = viewport.pixel_map();
+    let (width, height) = viewport.dims();
+    let expected: Vec<u32> = (0..width * height)
+        .map(|idx| {
+            let x = (idx % width) as f64 - width as f64 / 2.0;
+            let y = (idx / width) as f64 - height as f64 / 2.0;
+            let c = Complex {
+                re: viewport.precise_center.re + Fixed::from_f64(x * map.dx.re + y * map.dy.re),
+                im: viewport.precise_center.im + Fixed::from_f64(x * map.dx.im + y * map.dy.im),
+            };
+            fixed_escapes(c, limit) as u32
+        })
+        .collect();
+    // the views are centered on structure that f64 sees as a single point, yet their
+    // pixels escape at different counts
+    assert!(expected.iter().filter(|&&count| count < limit as u32).count() > 1, "{:?}", expected);
+    expected
+}
+
+#[test]
+fn perturbation_matches_fixed_point() {
+    let renderer = PerturbationMandelbrot::new().unwrap();
+    // pixels of 2.5e-301 take the FloatExp path
+    for &(scale, dims, limit) in &[(1e-30, (8, 6), 400), (1e-300, (2, 2), 4000)] {
+        let viewport = Viewport::new(Complex::new(0.0, 1.0), scale, dims);
+        let expected = fixed_reference(&viewport, limit);
+        let counts = renderer.render(&viewport, limit as usize).unwrap();
+        let mismatched = counts.iter().zip(&expected).filter(|(a, b)| a != b).count();
+        assert!(mismatched <= 1, "scale {}: {:?} against {:?}", scale, counts, expected);
+    }
+}
+
+#[test]
+fn perturbation_rebases_deep_minibrot() {
+    // nucleus of the period 140 minibrot next to -2, about 1.6e-167 across; the reference
+    // orbit from it comes back to within 1e-157 of 0
+    let nucleus: Fixed = concat!(
+        "-1.999999999999999999999999999999999999999999999999999999999999999999999999999999999992379346444018811",
+        "7856261996000166688177649499325639564332428542210419407143555150090643422213653512956556977580909246",
+        "74967209845742235856475813205830508990783",
+    ).parse().unwrap();
+    let mut viewport = Viewport::new(Complex::new(-2.0, 0.0), 6e-167, (8, 6));
+    viewport.set_center(Complex { re: nucleus, im: Fixed::ZERO });
+    let expected = fixed_reference(&viewport, 2500);
+    let counts = PerturbationMandelbrot::new().unwrap().render(&viewport, 2500).unwrap();
+    let mismatched = counts.iter().zip(&expected).filter(|(a, b)| a != b).count();
+    assert!(mismatched <= 1, "{:?} against {:?}", counts, expected);
+}
+
+#[test]
+fn double_double_matches_fixed_point() {
+    let viewport = Viewport::new(Complex::new(0.0, 1.0), 1e-28, (8, 6));
+    let expected = fixed_reference(&viewport, 400);
+    for backend in available_renderers().iter().filter(|backend| backend.capabilities.precision == Precision::DoubleDouble) {
+        let counts = backend.renderer.render(&viewport, 400).unwrap();
+        let mismatched = counts.iter().zip(&expected).filter(|(a, b)| a != b).count();
+        assert!(mismatched <= 1, "{}: {:?} against {:?}", backend.name, counts, expected);
+    }
+}
+
+#[test]
+fn opencl_picks_precision_for_zoom() {
+    // pixels of 1.25e-10, far below what f32 tells apart
+    let deep = Viewport::new(Complex::new(0.0, 1.0), 1e-9, (8, 6));
+    let expected = fixed_reference(&deep, 400);
+    for &forced in &[None, Some(Precision::F64), Some(Precision::DoubleFloat)] {
+        let renderer = match OCLMandelbrot::with_precision(forced) {
+            Ok(renderer) => renderer,
+            Err(e) => {
+                println!("skipping opencl in {:?}: {}", forced, e);
+                continue;
+            }
+        };
+        let shallow = Viewport::new(Complex::new(-0.5, 0.0), 3.0, (96, 64));
+        let used = renderer.precision_for(&deep);
+        assert_eq!(renderer.precision_for(&shallow), forced.unwrap_or(Precision::F32));
+        assert_ne!(used, Precision::F32);
+        assert_eq!(MandelbrotRenderer::precision(&renderer, &deep, 400), Some(used));
+
+        let counts = renderer.render(&deep, 400).unwrap();
+        let mismatched = counts.iter().zip(&expected).filter(|(a, b)| a != b).count();
+        assert!(mismatched <= 1, "{:?}: {:?} against {:?}", used, counts, expected);
+    }
+}
+
+#[test]
+fn deep_pan_moves_precise_center() {
+    let mut viewport = Viewport::new(Complex::new(0.0, 1.0), 1e-30, (64, 64));
+    let start = viewport;
+    viewport.pan(3.0, -2.0);
+    // far below the f64 spacing around 1, only the precise center moves
+    assert_eq!(viewport.center, start.center);
+    assert_ne!(viewport.precise_center, start.precise_center);
+    assert_eq!(viewport.pixel_offset(&start), Some((3, -2)));
+}
+
+/// Renders views across the real axis, whole and in progressive grids, with `R` and
+/// with `R` behind `Mirrored`, which must give the same values bit for bit.
+fn mirrored_matches_full<R: MandelbrotRenderer>() {
+    let (plain, mirrored) = match (R::new(), Mirrored::<R>::new()) {
+        (Ok(plain), Ok(mirrored)) => (plain, mirrored),
+        // not on this machine
+        _ => return,
+    };
+    let views = [
+        Viewport::new(Complex::new(-0.5, 0.0), 3.0, (96, 64)),
+        Viewport::new(Complex::new(-0.75, 0.01), 0.3, (64, 48)),
+        Viewport::new(Complex::new(-1.25, 0.0), 0.05, (40, 25)),
+    ];
+    let params = RenderParams::new(LIMIT);
+    for viewport in views.iter() {
+        for tile in [Tile::full(viewport), Tile::grid(viewport, 4, 2, 1), Tile::grid(viewport, 2, 1, 1)].iter() {
+            let mut expected = vec![0u32; tile.len()];
+            let mut got = vec![0u32; tile.len()];
+            plain.render_tile(viewport, tile, &params, Counts::U32(&mut expected)).unwrap();
+            mirrored.render_tile(viewport, tile, &params, Counts::U32(&mut got)).unwrap();
+            assert_eq!(got, expected, "{:?} at {:?}", tile, viewport);
+
+            let mut expected = vec![0.0; tile.len()];
+            let mut got = vec![0.0; tile.len()];
+            plain.render_tile_smooth(viewport, tile, &params, &mut expected).unwrap();
+            mirrored.render_tile_smooth(viewport, tile, &params, &mut got).unwrap();
+            let bits = |values: &[f32]| values.iter().map(|value| value.to_bits()).collect::<Vec<_>>();
+            assert_eq!(bits(&got), bits(&expected), "{:?} at {:?}", tile, viewport);
+        }
+        assert_eq!(mirrored.precision(viewport, LIMIT), plain.precision(viewport, LIMIT));
+    }
+}
+
+#[test]
+fn mirrored_matches_every_backend() {
+    mirrored_matches_full::<SingleMandelbrot>();
+    mirrored_matches_full::<MultiMandelbrot>();
+    mirrored_matches_full::<SIMDMandelbrot>();
+    mirrored_matches_full::<OCLMandelbrot>();
+    mirrored_matches_full::<MarianiSilver>();
+    mirrored_matches_full::<DDSingleMandelbrot>();
+    mirrored_matches_full::<DDSIMDMandelbrot>();
+    mirrored_matches_full::<PerturbationMandelbrot>();
+}
@@ -15,8 +15,9 @@
 pub const WINDOW_WIDTH: u32 = 1600;
 pub const WINDOW_HEIGHT: u32 = 1024;
 
-pub const FRACTAL_CENTER_X: f64 = -1.2477421233060082; // X center position
-pub const FRACTAL_CENTER_Y: f64 = 0.03592797277347884; // Y center position
+// decimal strings, parsed into ggez_mandel::Fixed so no digits are lost
+pub const FRACTAL_CENTER_X: &str = "-1.2477421233060082"; // X center position
+pub const FRACTAL_CENTER_Y: &str = "0.03592797277347884"; // Y center position
 
 pub const ZOOM: f64 = 1.0
 ; // Y center position
@@ -25,3 +26,11 @@ pub const LIMIT: f64 = 1500.; // Y cen  ter position
 pub const FRACTAL_SCALE: f32 = 1.0;
 
 
+pub const DEFAULT_RENDERER: &str = "simd"; // name in ggez_mandel::Registry
+
+pub const LOCATION_FILE: &str = "location.txt"; // written by the L key, read by --location
+
+// the view scale is an f64, see ggez_mandel::PerturbationMandelbrot. The center is a
+// Fixed of 1248 fraction bits, fixed at compile time by fixed::LIMBS: it holds pixels
+// down to about 4e-357 (fixed::resolves), so the f64 scale is the ceiling that counts
+pub const MIN_ZOOM: f64 = 1e-295;
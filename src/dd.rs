@@ -0,0 +1,178 @@
+use crate::fixed::Fixed;
+use crate::kernel::Float;
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// Unevaluated sum `hi + lo` of two f64 with `|lo| <= ulp(hi) / 2`, about 106 bits of
+/// mantissa. `T` is `f64x8` for eight values at once, or f32 lanes for double-float
+/// pairs of about 48 bits.
+///
+/// Only the error-free transformations of plain `+`, `-` and `*` are used (Dekker's
+/// product with the `Float::SPLITTER` of the lanes, no fused multiply-add), so the cost
+/// does not depend on the target features. Values past 2^996 (2^115 in f32) overflow
+/// while being split.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DoubleDouble<T = f64> {
+    pub hi: T,
+    pub lo: T,
+}
+
+/// `a + b` exactly, as the rounded sum and its error.
+#[inline]
+fn two_sum<T: Float>(a: T, b: T) -> DoubleDouble<T> {
+    let hi = a + b;
+    let b_virtual = hi - a;
+    let lo = (a - (hi - b_virtual)) + (b - b_virtual);
+    DoubleDouble { hi, lo }
+}
+
+/// `two_sum` for `|a| >= |b|`.
+#[inline]
+fn quick_two_sum<T: Float>(a: T, b: T) -> DoubleDouble<T> {
+    let hi = a + b;
+    DoubleDouble { hi, lo: b - (hi - a) }
+}
+
+#[inline]
+fn split<T: Float>(a: T) -> (T, T) {
+    let t = T::splat(T::SPLITTER) * a;
+    let hi = t - (t - a);
+    (hi, a - hi)
+}
+
+/// `a * b` exactly, as the rounded product and its error.
+#[inline]
+pub fn two_prod<T: Float>(a: T, b: T) -> DoubleDouble<T> {
+    let hi = a * b;
+    let (a_hi, a_lo) = split(a);
+    let (b_hi, b_lo) = split(b);
+    let lo = ((a_hi * b_hi - hi) + a_hi * b_lo + a_lo * b_hi) + a_lo * b_lo;
+    DoubleDouble { hi, lo }
+}
+
+impl<T: Float> DoubleDouble<T> {
+    #[inline]
+    pub fn splat(value: f64) -> DoubleDouble<T> {
+        DoubleDouble { hi: T::splat(value), lo: T::splat(0.0) }
+    }
+
+    #[inline]
+    pub fn square(self) -> DoubleDouble<T> {
+        let product = two_prod(self.hi, self.hi);
+        let cross = self.hi * self.lo;
+        quick_two_sum(product.hi, product.lo + (cross + cross))
+    }
+}
+
+impl DoubleDouble {
+    /// Nearest double-double, the digits of `value` past 106 bits are dropped.
+    pub fn from_fixed(value: &Fixed) -> DoubleDouble {
+        let hi = value.to_f64();
+        let lo = (*value - Fixed::from_f64(hi)).to_f64();
+        two_sum(hi, lo)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(value: f64) -> DoubleDouble {
+        DoubleDouble { hi: value, lo: 0.0 }
+    }
+}
+
+impl<T: Float> Add for DoubleDouble<T> {
+    type Output = DoubleDouble<T>;
+    #[inline]
+    fn add(self, other: DoubleDouble<T>) -> DoubleDouble<T> {
+        let high = two_sum(self.hi, other.hi);
+        let low = two_sum(self.lo, other.lo);
+        let sum = quick_two_sum(high.hi, high.lo + low.hi);
+        quick_two_sum(sum.hi, sum.lo + low.lo)
+    }
+}
+
+impl<T: Float> Neg for DoubleDouble<T> {
+    type Output = DoubleDouble<T>;
+    #[inline]
+    fn neg(self) -> DoubleDouble<T> {
+        DoubleDouble { hi: -self.hi, lo: -self.lo }
+    }
+}
+
+impl<T: Float> Sub for DoubleDouble<T> {
+    type Output = DoubleDouble<T>;
+    #[inline]
+    fn sub(self, other: DoubleDouble<T>) -> DoubleDouble<T> {
+        self + -other
+    }
+}
+
+impl<T: Float> Mul for DoubleDouble<T> {
+    type Output = DoubleDouble<T>;
+    #[inline]
+    fn mul(self, other: DoubleDouble<T>) -> DoubleDouble<T> {
+        let product = two_prod(self.hi, other.hi);
+        quick_two_sum(product.hi, product.lo + (self.hi * other.lo + self.lo * other.hi))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use packed_simd::f64x8;
+
+    #[test]
+    fn matches_f64() {
+        let values = [0.5, -0.75, 1.25, -1.999, 0.1, 3.0e-9];
+        for &a in &values {
+            for &b in &values {
+                let (da, db) = (DoubleDouble::from(a), DoubleDouble::from(b));
+                assert_eq!((da + db).hi, a + b, "{} + {}", a, b);
+                assert_eq!((da - db).hi, a - b, "{} - {}", a, b);
+                assert_eq!((da * db).hi, a * b, "{} * {}", a, b);
+                assert_eq!(da.square().hi, a * a, "{}^2", a);
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_digits_past_f64() {
+        // (1 + 2^-80)^2 = 1 + 2^-79 + 2^-160, the last term is below the 106 bits
+        let x = DoubleDouble::from(1.0) + DoubleDouble::from(2f64.powi(-80));
+        assert_eq!(x.hi, 1.0);
+        assert_eq!(x * x - DoubleDouble::from(1.0), DoubleDouble::from(2f64.powi(-79)));
+        assert_eq!(x.square(), x * x);
+        // 0.1 has digits past f64, which survive the round trip from Fixed
+        let tenth = Fixed::from_f64(0.1) + Fixed::from_f64(0.1 * 2f64.powi(-60));
+        let dd = DoubleDouble::from_fixed(&tenth);
+        assert_eq!(dd.hi, 0.1);
+        assert_eq!(dd.lo, 0.1 * 2f64.powi(-60));
+    }
+
+    #[test]
+    fn products_exact_in_f32() {
+        // the product of two f32 fits the 53 bits of f64
+        let values = [0.1f32, -1.999, 3.0e-5, 16_777_215.0, 1.0 / 3.0];
+        for &a in &values {
+            for &b in &values {
+                let product = two_prod(a, b);
+                assert_eq!(product.hi as f64 + product.lo as f64, a as f64 * b as f64, "{} * {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn lanes_match_scalar() {
+        let a = DoubleDouble { hi: f64x8::new(0.5, -0.75, 1.25, -1.999, 0.1, 3.0e-9, 2.0, -0.0), lo: f64x8::splat(1e-20) };
+        let b = DoubleDouble { hi: f64x8::splat(0.3), lo: f64x8::splat(-2e-19) };
+        let product = a * b + a.square() - b;
+        for lane in 0..8 {
+            let scalar_a = DoubleDouble { hi: a.hi.extract(lane), lo: a.lo.extract(lane) };
+            let scalar_b = DoubleDouble { hi: b.hi.extract(lane), lo: b.lo.extract(lane) };
+            let scalar = scalar_a * scalar_b + scalar_a.square() - scalar_b;
+            assert_eq!((product.hi.extract(lane), product.lo.extract(lane)), (scalar.hi, scalar.lo));
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use crate::dd::DoubleDouble;
+use crate::dd_single::DDPixelMap;
+use crate::error::RenderError;
+use crate::kernel;
+use crate::renderer::{MandelbrotRenderer, RenderParams, Count, Counts, check_tile, check_limit};
+use crate::viewport::{Viewport, Tile};
+use num::Complex;
+use packed_simd::*;
+use rayon::prelude::*;
+
+/// `SIMDMandelbrot` in double-double arithmetic: eight pixels per `f64x8` pair, rows in
+/// parallel.
+pub struct DDSIMDMandelbrot;
+
+/// The first `lanes` of the eight horizontally adjacent samples of `tile` starting at
+/// sample (i, j). Lanes past the end of the row are 0, in the main cardioid, so the
+/// interior shortcut retires them before the first iteration.
+#[inline]
+fn from_pixels(map: &DDPixelMap, tile: &Tile, i: usize, j: usize, lanes: usize) -> Complex<DoubleDouble<f64x8>> {
+    let mut parts = [[0f64; 8]; 4];
+    for lane in 0..lanes {
+        let (x, y) = tile.pixel(i + lane, j);
+        let c = map.at(x, y);
+        parts[0][lane] = c.re.hi;
+        parts[1][lane] = c.re.lo;
+        parts[2][lane] = c.im.hi;
+        parts[3][lane] = c.im.lo;
+    }
+    let load = |part: &[f64; 8]| f64x8::from_slice_unaligned(part);
+    Complex {
+        re: DoubleDouble { hi: load(&parts[0]), lo: load(&parts[1]) },
+        im: DoubleDouble { hi: load(&parts[2]), lo: load(&parts[3]) },
+    }
+}
+
+impl DDSIMDMandelbrot {
+    fn map_blocks<T, F>(viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [T], f: F) -> Result<(), RenderError>
+        where T: Send, F: Fn(Complex<DoubleDouble<f64x8>>, &mut [T]) + Sync + Send
+    {
+        check_tile(viewport, tile, out.len())?;
+        let block_size = f64x8::lanes();
+        let map = DDPixelMap::new(viewport);
+
+        out.par_chunks_mut(tile.width).enumerate().try_for_each(|(i, row)| {
+            params.cancel.check()?;
+            row.chunks_mut(block_size).enumerate().for_each(|(j, block)| {
+                let lanes = block.len();
+                f(from_pixels(&map, tile, j * block_size, i, lanes), block);
+            });
+            Ok(())
+        })
+    }
+
+    fn counts<C: Count>(viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [C]) -> Result<(), RenderError> {
+        check_limit::<C>(params.limit)?;
+        let limit = params.limit as u64;
+        Self::map_blocks(viewport, tile, params, out, |c, counts| {
+            let lanes = kernel::escapes(c, limit, true);
+            for (lane, count) in counts.iter_mut().enumerate() {
+                *count = C::from_count(lanes.extract(lane) as u64);
+            }
+        })
+    }
+}
+
+impl MandelbrotRenderer for DDSIMDMandelbrot {
+    fn new() -> Result<DDSIMDMandelbrot, RenderError> {
+        Ok(DDSIMDMandelbrot)
+    }
+    fn render_tile(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: Counts) -> Result<(), RenderError> {
+        match out {
+            Counts::U16(out) => Self::counts(viewport, tile, params, out),
+            Counts::U32(out) => Self::counts(viewport, tile, params, out),
+            Counts::F32(out) => Self::counts(viewport, tile, params, out),
+        }
+    }
+    fn render_tile_smooth(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [f32]) -> Result<(), RenderError> {
+        let limit = params.limit as u64;
+        Self::map_blocks(viewport, tile, params, out, |c, smooth| {
+            kernel::escapes_smooth(c, limit, true, smooth);
+        })
+    }
+}
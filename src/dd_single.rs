@@ -0,0 +1,95 @@
+use crate::dd::{DoubleDouble, two_prod};
+use crate::error::RenderError;
+use crate::kernel;
+use crate::renderer::{MandelbrotRenderer, RenderParams, Count, Counts, check_tile, check_limit};
+use crate::viewport::{Viewport, Tile};
+use num::Complex;
+
+/// `SingleMandelbrot` in double-double arithmetic, good for zooms to about 1e-30 at a
+/// fraction of the speed.
+pub struct DDSingleMandelbrot;
+
+/// Pixel -> complex mapping in double-double, from the precise center of the viewport.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct DDPixelMap {
+    center: Complex<DoubleDouble>,
+    dx: Complex<f64>,
+    dy: Complex<f64>,
+    half_w: f64,
+    half_h: f64,
+}
+
+impl DDPixelMap {
+    pub(crate) fn new(viewport: &Viewport) -> DDPixelMap {
+        let map = viewport.pixel_map();
+        DDPixelMap {
+            center: Complex {
+                re: DoubleDouble::from_fixed(&viewport.precise_center.re),
+                im: DoubleDouble::from_fixed(&viewport.precise_center.im),
+            },
+            dx: map.dx,
+            dy: map.dy,
+            half_w: viewport.width as f64 / 2.0,
+            half_h: viewport.height as f64 / 2.0,
+        }
+    }
+
+    /// Point of pixel (x, y); the offset from the center is exact up to the rounding of
+    /// the pixel steps.
+    #[inline]
+    pub(crate) fn at(&self, x: usize, y: usize) -> Complex<DoubleDouble> {
+        let (x, y) = (x as f64 - self.half_w, y as f64 - self.half_h);
+        Complex {
+            re: self.center.re + two_prod(x, self.dx.re) + two_prod(y, self.dy.re),
+            im: self.center.im + two_prod(x, self.dx.im) + two_prod(y, self.dy.im),
+        }
+    }
+}
+
+impl DDSingleMandelbrot {
+    fn map_pixels<T, F: Fn(Complex<DoubleDouble>) -> T>(viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [T], f: F) -> Result<(), RenderError> {
+        check_tile(viewport, tile, out.len())?;
+        let map = DDPixelMap::new(viewport);
+        for (j, row) in out.chunks_mut(tile.width).enumerate() {
+            params.cancel.check()?;
+            for (i, value) in row.iter_mut().enumerate() {
+                let (x, y) = tile.pixel(i, j);
+                *value = f(map.at(x, y));
+            }
+        }
+        Ok(())
+    }
+
+    fn counts<C: Count>(viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [C]) -> Result<(), RenderError> {
+        check_limit::<C>(params.limit)?;
+        let limit = params.limit as u64;
+        Self::map_pixels(viewport, tile, params, out, |point| C::from_count(escapes(point, limit)))
+    }
+}
+
+impl MandelbrotRenderer for DDSingleMandelbrot {
+    fn new() -> Result<DDSingleMandelbrot, RenderError> {
+        Ok(DDSingleMandelbrot)
+    }
+    fn render_tile(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: Counts) -> Result<(), RenderError> {
+        match out {
+            Counts::U16(out) => Self::counts(viewport, tile, params, out),
+            Counts::U32(out) => Self::counts(viewport, tile, params, out),
+            Counts::F32(out) => Self::counts(viewport, tile, params, out),
+        }
+    }
+    fn render_tile_smooth(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [f32]) -> Result<(), RenderError> {
+        let limit = params.limit as u64;
+        Self::map_pixels(viewport, tile, params, out, |c| {
+            let mut smooth = [0.0];
+            kernel::escapes_smooth(c, limit, true, &mut smooth);
+            smooth[0]
+        })
+    }
+}
+
+/// `single::escapes_fast` in double-double. The bailout is tested on the high parts.
+#[inline]
+pub fn escapes(c: Complex<DoubleDouble>, limit: u64) -> u64 {
+    kernel::escapes(c, limit, true) as u64
+}
@@ -0,0 +1,88 @@
+use std::error::Error;
+use std::fmt;
+use ocl::core::Status;
+use crate::registry::Precision;
+use crate::viewport::Tile;
+
+/// Everything that can go wrong while setting up or running a renderer.
+#[derive(Debug)]
+pub enum RenderError {
+    /// No OpenCL platform or device is installed.
+    NoPlatform,
+    /// The OpenCL program did not build; holds the build log.
+    KernelCompile(String),
+    /// The device ran out of memory for the output buffer.
+    OutOfMemory,
+    /// The viewport or tile has no pixels to render.
+    InvalidDims { width: usize, height: usize },
+    /// The tile reaches past the edge of the viewport.
+    TileOutOfBounds(Tile),
+    /// The output buffer does not hold one value per sample of the tile.
+    BufferSize { expected: usize, got: usize },
+    /// The iteration limit does not fit the count type of the output buffer, or the int
+    /// the OpenCL kernels count in.
+    LimitTooLarge { limit: usize, max: u64 },
+    /// Any other OpenCL failure.
+    OpenCL(String),
+    /// No backend is registered under this name.
+    UnknownRenderer(String),
+    /// The render was stopped through its `CancelToken`.
+    Cancelled,
+    /// The text is not a decimal number in range of a `Fixed`.
+    InvalidNumber(String),
+    /// A location file is missing a field or has one it does not know.
+    InvalidLocation(String),
+    /// Reading or writing a file failed.
+    Io(std::io::Error),
+    /// The backend, or its device, cannot iterate in this precision.
+    UnsupportedPrecision(Precision),
+    /// Rayon could not start the threads of a pool of its own, see `Registry::with_threads`.
+    ThreadPool(String),
+    /// A render panicked while it held this shared state of the backend.
+    Poisoned(&'static str),
+    /// The thread of a `RenderWorker` is gone, a render panicked on it.
+    WorkerStopped,
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::NoPlatform => write!(f, "no OpenCL platform or device available"),
+            RenderError::KernelCompile(log) => write!(f, "kernel failed to compile: {}", log),
+            RenderError::OutOfMemory => write!(f, "out of device memory"),
+            RenderError::InvalidDims { width, height } => write!(f, "invalid image dimensions {}x{}", width, height),
+            RenderError::TileOutOfBounds(tile) => write!(f, "tile {:?} is outside the viewport", tile),
+            RenderError::BufferSize { expected, got } => write!(f, "output buffer holds {} values, the tile has {}", got, expected),
+            RenderError::LimitTooLarge { limit, max } => write!(f, "limit {} does not fit the count type, at most {}", limit, max),
+            RenderError::OpenCL(msg) => write!(f, "opencl error: {}", msg),
+            RenderError::UnknownRenderer(name) => write!(f, "unknown renderer '{}'", name),
+            RenderError::Cancelled => write!(f, "render cancelled"),
+            RenderError::InvalidNumber(text) => write!(f, "invalid number '{}'", text),
+            RenderError::InvalidLocation(msg) => write!(f, "invalid location: {}", msg),
+            RenderError::Io(err) => write!(f, "io error: {}", err),
+            RenderError::UnsupportedPrecision(precision) => write!(f, "{:?} arithmetic is not supported here", precision),
+            RenderError::ThreadPool(msg) => write!(f, "could not start the thread pool: {}", msg),
+            RenderError::Poisoned(what) => write!(f, "{} lock poisoned by a failed render", what),
+            RenderError::WorkerStopped => write!(f, "the render worker stopped after a failed render"),
+        }
+    }
+}
+
+impl Error for RenderError {}
+
+impl From<ocl::Error> for RenderError {
+    fn from(err: ocl::Error) -> RenderError {
+        match err.api_status() {
+            Some(Status::CL_OUT_OF_RESOURCES)
+            | Some(Status::CL_OUT_OF_HOST_MEMORY)
+            | Some(Status::CL_MEM_OBJECT_ALLOCATION_FAILURE) => RenderError::OutOfMemory,
+            _ => RenderError::OpenCL(err.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for RenderError {
+    fn from(err: std::io::Error) -> RenderError {
+        RenderError::Io(err)
+    }
+}
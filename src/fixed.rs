@@ -0,0 +1,390 @@
+use crate::error::RenderError;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+use std::str::FromStr;
+
+/// 32-bit limbs of a [`Fixed`]: one integer limb and the fraction.
+pub const LIMBS: usize = 40;
+
+/// Fraction bits of a [`Fixed`], and so the decimal places of its exact expansion.
+pub const FRACTION_BITS: usize = 32 * (LIMBS - 1);
+
+/// Fraction bits a [`Fixed`] keeps below the pixels of a view, for the rounding of
+/// reference orbits.
+pub const GUARD_BITS: usize = 64;
+
+/// Whether [`Fixed`] coordinates still tell apart pixels of size `pixel`, with
+/// `GUARD_BITS` to spare: down to 2^-1184, about 4e-357, past the range of f64. A
+/// smaller `LIMBS` would bring the ceiling into it.
+pub fn resolves(pixel: f64) -> bool {
+    pixel > 0.0 && pixel.log2() >= -((FRACTION_BITS - GUARD_BITS) as f64)
+}
+
+/// Signed fixed-point number with a 32-bit integer part and `FRACTION_BITS` fraction
+/// bits, about 375 decimal digits.
+///
+/// Holds coordinates and reference orbits past the precision of f64 for deep zooms.
+///
+/// The precision is not arbitrary: it is fixed at compile time by `LIMBS`, 1248
+/// fraction bits, and zooms end where pixels get smaller than about 4e-357 (see
+/// [`resolves`]). A deeper ceiling takes a larger `LIMBS`. The f64 scale of the view
+/// ends zooms first, at about 1e-300, and the viewer checks both. It is `Copy` so that [`Viewport`](crate::Viewport) can stay
+/// `Copy`. Products are truncated towards zero and integer parts past 32 bits are lost,
+/// which the Mandelbrot bailout never gets near.
+///
+/// Decimal strings parse to the nearest value, and `Display` prints the fewest decimal
+/// places that parse back to the same value, so text round-trips exactly. A precision,
+/// as in `{:.20}`, rounds to that many places instead.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Fixed {
+    negative: bool,
+    /// Magnitude, most significant limb first. `limbs[0]` is the integer part.
+    limbs: [u32; LIMBS],
+}
+
+impl Fixed {
+    pub const ZERO: Fixed = Fixed { negative: false, limbs: [0; LIMBS] };
+
+    pub fn from_f64(value: f64) -> Fixed {
+        let mut limbs = [0; LIMBS];
+        let mut rest = value.abs();
+        for limb in limbs.iter_mut() {
+            // exact: `rest` keeps at most 53 significant bits
+            let whole = rest.floor();
+            *limb = whole as u32;
+            rest = (rest - whole) * 4_294_967_296.0;
+            if rest == 0.0 {
+                break;
+            }
+        }
+        Fixed { negative: value < 0.0, limbs }.normalized()
+    }
+
+    /// Nearest f64, up to the rounding of the three leading limbs.
+    pub fn to_f64(&self) -> f64 {
+        let first = match self.limbs.iter().position(|&limb| limb != 0) {
+            Some(first) => first,
+            None => return 0.0,
+        };
+        let leading = self.limbs[first..].iter()
+            .take(3)
+            .rev()
+            .fold(0.0, |acc, &limb| acc / 4_294_967_296.0 + limb as f64);
+        // limb k weighs 2^(-32k); dividing step by step keeps tiny values subnormal
+        let value = (0..first).fold(leading, |acc, _| acc / 4_294_967_296.0);
+        if self.negative { -value } else { value }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.iter().all(|&limb| limb == 0)
+    }
+
+    /// Zero has no sign, so that equal values compare equal.
+    fn normalized(mut self) -> Fixed {
+        if self.is_zero() {
+            self.negative = false;
+        }
+        self
+    }
+
+    /// `self * self`, with each cross product computed once.
+    pub fn square(self) -> Fixed {
+        let mut columns = [0u128; LIMBS];
+        // the limbs past the middle only square into columns that are dropped
+        for (i, &a) in self.limbs.iter().enumerate().take(LIMBS.div_ceil(2)) {
+            if a == 0 {
+                continue;
+            }
+            let a = a as u128;
+            columns[2 * i] += a * a;
+            for (j, &b) in self.limbs.iter().enumerate().take(LIMBS - i).skip(i + 1) {
+                columns[i + j] += 2 * a * b as u128;
+            }
+        }
+        Fixed::from_columns(columns, false)
+    }
+
+    /// Carries the column sums of a product into limbs, column k weighs 2^(-32k) like
+    /// limb k. The carry out of column 0 is past the integer part and lost.
+    fn from_columns(mut columns: [u128; LIMBS], negative: bool) -> Fixed {
+        for i in (1..LIMBS).rev() {
+            let carry = columns[i] >> 32;
+            columns[i] &= 0xffff_ffff;
+            columns[i - 1] += carry;
+        }
+        let mut limbs = [0; LIMBS];
+        for (limb, &column) in limbs.iter_mut().zip(columns.iter()) {
+            *limb = column as u32;
+        }
+        Fixed { negative, limbs }.normalized()
+    }
+
+    /// The integer part and the fraction rounded to `places` decimal digits; rounding
+    /// up may carry into the integer part.
+    fn decimal(&self, places: usize) -> (u64, Vec<u8>) {
+        let mut fraction = [0u32; LIMBS - 1];
+        fraction.copy_from_slice(&self.limbs[1..]);
+        let mut digits = Vec::with_capacity(places + 1);
+        for _ in 0..=places {
+            let mut carry = 0u64;
+            for limb in fraction.iter_mut().rev() {
+                let product = *limb as u64 * 10 + carry;
+                *limb = product as u32;
+                carry = product >> 32;
+            }
+            digits.push(carry as u8);
+        }
+        let mut integer = self.limbs[0] as u64;
+        if digits.pop().unwrap_or(0) >= 5 {
+            match digits.iter().rposition(|&digit| digit != 9) {
+                Some(last) => {
+                    digits[last] += 1;
+                    for digit in &mut digits[last + 1..] {
+                        *digit = 0;
+                    }
+                }
+                None => {
+                    integer += 1;
+                    for digit in digits.iter_mut() {
+                        *digit = 0;
+                    }
+                }
+            }
+        }
+        (integer, digits)
+    }
+
+    fn to_decimal(&self, places: usize) -> String {
+        let (integer, digits) = self.decimal(places);
+        let sign = if self.negative && (integer != 0 || digits.iter().any(|&digit| digit != 0)) { "-" } else { "" };
+        let mut text = format!("{}{}", sign, integer);
+        if !digits.is_empty() {
+            text.push('.');
+            text.extend(digits.iter().map(|&digit| (b'0' + digit) as char));
+        }
+        text
+    }
+
+    /// Fewest decimal places that parse back to `self`. The expansion with
+    /// `FRACTION_BITS` places is exact, shorter ones are searched by bisection.
+    fn shortest_places(&self) -> usize {
+        let (mut low, mut high) = (0, FRACTION_BITS);
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.to_decimal(mid).parse::<Fixed>().map_or(false, |parsed| parsed == *self) {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        low
+    }
+
+    fn cmp_magnitude(&self, other: &Fixed) -> Ordering {
+        self.limbs.cmp(&other.limbs)
+    }
+
+    fn add_magnitude(a: &[u32; LIMBS], b: &[u32; LIMBS]) -> [u32; LIMBS] {
+        let mut out = [0; LIMBS];
+        let mut carry = 0u64;
+        for i in (0..LIMBS).rev() {
+            let sum = a[i] as u64 + b[i] as u64 + carry;
+            out[i] = sum as u32;
+            carry = sum >> 32;
+        }
+        out
+    }
+
+    /// `a - b` for `a >= b`.
+    fn sub_magnitude(a: &[u32; LIMBS], b: &[u32; LIMBS]) -> [u32; LIMBS] {
+        let mut out = [0; LIMBS];
+        let mut borrow = 0i64;
+        for i in (0..LIMBS).rev() {
+            let mut diff = a[i] as i64 - b[i] as i64 - borrow;
+            borrow = 0;
+            if diff < 0 {
+                diff += 1 << 32;
+                borrow = 1;
+            }
+            out[i] = diff as u32;
+        }
+        out
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+    fn add(self, other: Fixed) -> Fixed {
+        if self.negative == other.negative {
+            return Fixed { negative: self.negative, limbs: Fixed::add_magnitude(&self.limbs, &other.limbs) };
+        }
+        let (larger, smaller) = match self.cmp_magnitude(&other) {
+            Ordering::Less => (other, self),
+            _ => (self, other),
+        };
+        Fixed {
+            negative: larger.negative,
+            limbs: Fixed::sub_magnitude(&larger.limbs, &smaller.limbs),
+        }.normalized()
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+    fn neg(self) -> Fixed {
+        Fixed { negative: !self.negative, ..self }.normalized()
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+    fn sub(self, other: Fixed) -> Fixed {
+        self + -other
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+    fn mul(self, other: Fixed) -> Fixed {
+        // column sums of the schoolbook product, column i + j holds limbs i and j;
+        // columns past LIMBS - 1 fall below the last limb and are dropped
+        let mut columns = [0u128; LIMBS];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            if a == 0 {
+                continue;
+            }
+            for (j, &b) in other.limbs.iter().enumerate().take(LIMBS - i) {
+                columns[i + j] += a as u128 * b as u128;
+            }
+        }
+        Fixed::from_columns(columns, self.negative != other.negative)
+    }
+}
+
+impl FromStr for Fixed {
+    type Err = RenderError;
+
+    /// Parses `[-+]digits[.digits]` to the nearest value.
+    fn from_str(text: &str) -> Result<Fixed, RenderError> {
+        let invalid = || RenderError::InvalidNumber(text.to_string());
+        let unsigned = text.trim();
+        let (negative, unsigned) = match unsigned.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, unsigned.strip_prefix('+').unwrap_or(unsigned)),
+        };
+        let (whole, fraction) = match unsigned.find('.') {
+            Some(dot) => (&unsigned[..dot], &unsigned[dot + 1..]),
+            None => (unsigned, ""),
+        };
+        if (whole.is_empty() && fraction.is_empty()) || !whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let integer = if whole.is_empty() { 0 } else { whole.parse::<u32>().map_err(|_| invalid())? };
+
+        // Horner from the last digit, x = (digit + x) / 10. The two guard limbs keep the
+        // truncation of every step below the rounding.
+        let mut guarded = [0u32; LIMBS + 1];
+        for digit in fraction.bytes().rev() {
+            let mut remainder = (digit - b'0') as u64;
+            for limb in guarded.iter_mut() {
+                let current = (remainder << 32) | *limb as u64;
+                *limb = (current / 10) as u32;
+                remainder = current % 10;
+            }
+        }
+        let mut limbs = [0; LIMBS];
+        limbs[0] = integer;
+        limbs[1..].copy_from_slice(&guarded[..LIMBS - 1]);
+        if guarded[LIMBS - 1] >= 1 << 31 {
+            let mut last = [0; LIMBS];
+            last[LIMBS - 1] = 1;
+            limbs = Fixed::add_magnitude(&limbs, &last);
+        }
+        Ok(Fixed { negative, limbs }.normalized())
+    }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let places = f.precision().unwrap_or_else(|| self.shortest_places());
+        f.write_str(&self.to_decimal(places))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips_f64() {
+        for &x in &[0.0, 1.0, -2.0, 0.1, -1.2477421233060082, 3.5e-12, 1.0 / 3.0] {
+            assert_eq!(Fixed::from_f64(x).to_f64(), x);
+        }
+    }
+
+    #[test]
+    fn arithmetic_matches_f64() {
+        // to_f64 may round differently from f64 in the last bit
+        let close = |x: f64, y: f64| (x - y).abs() <= f64::EPSILON * y.abs();
+        let values = [0.5, -0.75, 1.25, -1.999, 0.1, 3.0e-9];
+        for &a in &values {
+            for &b in &values {
+                let (fa, fb) = (Fixed::from_f64(a), Fixed::from_f64(b));
+                assert!(close((fa + fb).to_f64(), a + b), "{} + {}", a, b);
+                assert!(close((fa - fb).to_f64(), a - b), "{} - {}", a, b);
+                assert!(close((fa * fb).to_f64(), a * b), "{} * {}", a, b);
+            }
+        }
+        assert!((Fixed::from_f64(0.5) - Fixed::from_f64(0.5)).is_zero());
+        // products carry into the integer limb
+        assert_eq!(Fixed::from_f64(1.0) * Fixed::from_f64(1.0), Fixed::from_f64(1.0));
+        assert_eq!(Fixed::from_f64(3.0) * Fixed::from_f64(-0.5), Fixed::from_f64(-1.5));
+        assert_eq!(Fixed::from_f64(1.25).square(), Fixed::from_f64(1.5625));
+        assert_eq!(Fixed::from_f64(1.5).square(), Fixed::from_f64(2.25));
+    }
+
+    #[test]
+    fn keeps_digits_past_f64() {
+        // 1 + 2^-200 survives, and squares to 1 + 2^-199 + 2^-400
+        let tiny = Fixed::from_f64(2f64.powi(-200));
+        let x = Fixed::from_f64(1.0) + tiny;
+        assert_ne!(x, Fixed::from_f64(1.0));
+        assert_eq!(x - Fixed::from_f64(1.0), tiny);
+        assert_eq!(x * x - Fixed::from_f64(1.0) - tiny - tiny, Fixed::from_f64(2f64.powi(-400)));
+        assert_eq!(x.square(), x * x);
+        let y = Fixed::from_f64(-1.2477421233060082) + tiny;
+        assert_eq!(y.square(), y * y);
+    }
+
+    #[test]
+    fn resolves_views_down_to_min_zoom() {
+        // the deepest view of the viewer, however many pixels wide
+        assert!(resolves(crate::constants::MIN_ZOOM / 1e6));
+        assert!(resolves(f64::MIN_POSITIVE));
+        assert!(!resolves(0.0));
+    }
+
+    #[test]
+    fn parses_and_formats_decimals() {
+        let digits = "-1.24774212330600821234567890123456789012345678901234567890123456789";
+        let x: Fixed = digits.parse().unwrap();
+        assert_eq!(x.to_string(), digits);
+        assert_eq!(x.to_string().parse::<Fixed>().unwrap(), x);
+        assert!((x.to_f64() - -1.2477421233060082).abs() < 1e-15);
+
+        assert_eq!("0.5".parse::<Fixed>().unwrap(), Fixed::from_f64(0.5));
+        assert_eq!("+3".parse::<Fixed>().unwrap().to_string(), "3");
+        assert_eq!(".25".parse::<Fixed>().unwrap().to_string(), "0.25");
+        assert_eq!("-0.000".parse::<Fixed>().unwrap(), Fixed::ZERO);
+        assert_eq!(format!("{:.3}", "0.12951".parse::<Fixed>().unwrap()), "0.130");
+        assert_eq!(format!("{:.2}", "-9.999".parse::<Fixed>().unwrap()), "-10.00");
+        for bad in &["", "-", ".", "1.2.3", "1e5", "--1", "99999999999"] {
+            assert!(bad.parse::<Fixed>().is_err(), "{:?}", bad);
+        }
+
+        // a value with every fraction bit in use still round-trips
+        let third = Fixed::from_f64(1.0 / 3.0) * Fixed::from_f64(1.0 / 3.0) * Fixed::from_f64(1.0 / 3.0);
+        assert_eq!(third.to_string().parse::<Fixed>().unwrap(), third);
+    }
+}
@@ -0,0 +1,145 @@
+use std::cmp::Ordering;
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// An f64 mantissa with its own exponent, for values below the 1e-308 that f64 reaches.
+///
+/// The value is `mantissa * 2^exp` with `mantissa` in [0.5, 1), or zero. Only the
+/// arithmetic the perturbation deltas need is implemented.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FloatExp {
+    mantissa: f64,
+    exp: i64,
+}
+
+/// Exponent bits of an f64.
+const EXP_MASK: u64 = 0x7ff << 52;
+
+impl FloatExp {
+    pub const ZERO: FloatExp = FloatExp { mantissa: 0.0, exp: 0 };
+
+    pub fn new(mantissa: f64, exp: i64) -> FloatExp {
+        if mantissa == 0.0 || !mantissa.is_finite() {
+            return FloatExp { mantissa, exp: 0 };
+        }
+        let mut bits = mantissa.to_bits();
+        let mut exp = exp;
+        if bits & EXP_MASK == 0 {
+            // subnormal, scale it into the normal range first
+            bits = (mantissa * 2f64.powi(64)).to_bits();
+            exp -= 64;
+        }
+        let biased = ((bits & EXP_MASK) >> 52) as i64;
+        FloatExp {
+            mantissa: f64::from_bits((bits & !EXP_MASK) | (1022 << 52)),
+            exp: exp + biased - 1022,
+        }
+    }
+
+    pub fn from_f64(value: f64) -> FloatExp {
+        FloatExp::new(value, 0)
+    }
+
+    /// Nearest f64; zero below the subnormals, infinite past f64::MAX.
+    pub fn to_f64(self) -> f64 {
+        let exp = self.exp.max(-2200).min(2200) as i32;
+        // in two halves so that neither power of two overflows on its own
+        self.mantissa * 2f64.powi(exp / 2) * 2f64.powi(exp - exp / 2)
+    }
+
+    pub fn abs(self) -> FloatExp {
+        FloatExp { mantissa: self.mantissa.abs(), exp: self.exp }
+    }
+}
+
+impl PartialOrd for FloatExp {
+    fn partial_cmp(&self, other: &FloatExp) -> Option<Ordering> {
+        let (a, b) = (self.mantissa, other.mantissa);
+        // zeros, infinities and opposite signs are told apart by the mantissas alone
+        if self.exp == other.exp || a == 0.0 || b == 0.0 || !a.is_finite() || !b.is_finite() || (a < 0.0) != (b < 0.0) {
+            return a.partial_cmp(&b);
+        }
+        let larger = self.exp.cmp(&other.exp);
+        Some(if a < 0.0 { larger.reverse() } else { larger })
+    }
+}
+
+impl Add for FloatExp {
+    type Output = FloatExp;
+    fn add(self, other: FloatExp) -> FloatExp {
+        if self.mantissa == 0.0 {
+            return other;
+        }
+        if other.mantissa == 0.0 {
+            return self;
+        }
+        let (large, small) = if self.exp >= other.exp { (self, other) } else { (other, self) };
+        let shift = large.exp - small.exp;
+        if shift > 64 {
+            return large;
+        }
+        FloatExp::new(large.mantissa + small.mantissa * 2f64.powi(-shift as i32), large.exp)
+    }
+}
+
+impl Neg for FloatExp {
+    type Output = FloatExp;
+    fn neg(self) -> FloatExp {
+        FloatExp { mantissa: -self.mantissa, exp: self.exp }
+    }
+}
+
+impl Sub for FloatExp {
+    type Output = FloatExp;
+    fn sub(self, other: FloatExp) -> FloatExp {
+        self + -other
+    }
+}
+
+impl Mul for FloatExp {
+    type Output = FloatExp;
+    fn mul(self, other: FloatExp) -> FloatExp {
+        FloatExp::new(self.mantissa * other.mantissa, self.exp + other.exp)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips_f64() {
+        for &x in &[0.0, 1.0, -0.75, 3.0e-300, -1.0e300, 4.9e-324, 1.0 / 3.0] {
+            assert_eq!(FloatExp::from_f64(x).to_f64(), x);
+        }
+    }
+
+    #[test]
+    fn reaches_past_f64() {
+        let tiny = FloatExp::from_f64(1.0e-200);
+        let square = tiny * tiny;
+        assert_eq!(square.to_f64(), 0.0);
+        // 1e-400 * 1e300 is back in range
+        let back = (square * FloatExp::from_f64(1.0e300)).to_f64();
+        assert!((back - 1.0e-100).abs() < 1.0e-114);
+        let sum = ((square + square - square) * FloatExp::from_f64(1.0e300)).to_f64();
+        assert!((sum - back).abs() < 1.0e-114);
+        assert_eq!((FloatExp::from_f64(0.5) - FloatExp::from_f64(0.25)).to_f64(), 0.25);
+    }
+
+    #[test]
+    fn orders_like_f64() {
+        let values = [-1.0e300, -3.0, -0.75, -1.0e-300, 0.0, 4.9e-324, 1.0e-300, 0.75, 1.0, 3.0, f64::INFINITY];
+        for &a in &values {
+            for &b in &values {
+                let (x, y) = (FloatExp::from_f64(a), FloatExp::from_f64(b));
+                assert_eq!(x.partial_cmp(&y), a.partial_cmp(&b), "{} against {}", a, b);
+            }
+            assert_eq!(FloatExp::from_f64(a).abs().to_f64(), a.abs());
+        }
+        // past the range of f64
+        let tiny = FloatExp::from_f64(1.0e-200);
+        assert!(tiny * tiny < tiny * tiny * FloatExp::from_f64(2.0));
+        assert!(-(tiny * tiny) > -tiny);
+        assert!(tiny * tiny > FloatExp::ZERO);
+    }
+}
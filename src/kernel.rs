@@ -0,0 +1,469 @@
+//! The escape-time iteration shared by the CPU backends, generic over the numbers it runs
+//! in: f32 and f64, their `packed_simd` vectors, and double-double pairs of any of them,
+//! which are split for their products at the half of their own mantissa.
+//!
+//! Everything works lane-wise, a scalar is a vector of one lane. Counts are kept in the
+//! lanes' own float type, exact up to 2^24 iterations for f32 and 2^53 for f64.
+
+use crate::dd::DoubleDouble;
+use crate::renderer::smooth_count;
+use num::Complex;
+use packed_simd::{f32x8, f32x16, f64x2, f64x4, f64x8, m32x8, m32x16, m64x2, m64x4, m64x8};
+use std::ops::{Add, BitAnd, BitOr, Mul, Neg, Not, Sub};
+
+/// Lane-wise float operations, on a plain value or a SIMD vector.
+pub trait Float: Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Neg<Output = Self> + Send + Sync {
+    /// One bool per lane.
+    type Mask: Copy + BitAnd<Output = Self::Mask> + BitOr<Output = Self::Mask> + Not<Output = Self::Mask>;
+    const LANES: usize;
+    /// `2^s + 1` that splits the mantissa of a lane into halves of at most `s` bits for
+    /// Dekker's product, see [`DoubleDouble`].
+    const SPLITTER: f64;
+    fn splat(value: f64) -> Self;
+    /// The first `LANES` values, zero past the end of `values`.
+    fn from_lanes(values: &[f64]) -> Self;
+    fn extract(self, lane: usize) -> f64;
+    /// `self` with `lane` set to `value`.
+    fn replace(self, lane: usize, value: f64) -> Self;
+    fn greater(self, other: Self) -> Self::Mask;
+    fn less(self, other: Self) -> Self::Mask;
+    fn equal(self, other: Self) -> Self::Mask;
+    fn select(mask: Self::Mask, yes: Self, no: Self) -> Self;
+    fn mask(value: bool) -> Self::Mask;
+    /// Whether `lane` of `mask` is set.
+    fn test(mask: Self::Mask, lane: usize) -> bool;
+    fn all(mask: Self::Mask) -> bool;
+    fn any(mask: Self::Mask) -> bool;
+}
+
+macro_rules! scalar_float {
+    ($t:ty, $splitter:expr) => {
+        impl Float for $t {
+            type Mask = bool;
+            const LANES: usize = 1;
+            const SPLITTER: f64 = $splitter;
+            #[inline(always)]
+            fn splat(value: f64) -> $t {
+                value as $t
+            }
+            #[inline(always)]
+            fn from_lanes(values: &[f64]) -> $t {
+                values.first().map_or(0.0, |&value| value as $t)
+            }
+            #[inline(always)]
+            fn extract(self, _lane: usize) -> f64 {
+                self as f64
+            }
+            #[inline(always)]
+            fn replace(self, _lane: usize, value: f64) -> $t {
+                value as $t
+            }
+            #[inline(always)]
+            fn greater(self, other: $t) -> bool {
+                self > other
+            }
+            #[inline(always)]
+            fn less(self, other: $t) -> bool {
+                self < other
+            }
+            #[inline(always)]
+            #[allow(clippy::float_cmp)]
+            fn equal(self, other: $t) -> bool {
+                self == other
+            }
+            #[inline(always)]
+            fn select(mask: bool, yes: $t, no: $t) -> $t {
+                if mask { yes } else { no }
+            }
+            #[inline(always)]
+            fn mask(value: bool) -> bool {
+                value
+            }
+            #[inline(always)]
+            fn test(mask: bool, _lane: usize) -> bool {
+                mask
+            }
+            #[inline(always)]
+            fn all(mask: bool) -> bool {
+                mask
+            }
+            #[inline(always)]
+            fn any(mask: bool) -> bool {
+                mask
+            }
+        }
+    };
+}
+
+macro_rules! vector_float {
+    ($t:ident, $m:ident, $lane:ty, $lanes:expr) => {
+        impl Float for $t {
+            type Mask = $m;
+            const LANES: usize = $lanes;
+            const SPLITTER: f64 = <$lane as Float>::SPLITTER;
+            #[inline(always)]
+            fn splat(value: f64) -> $t {
+                $t::splat(value as $lane)
+            }
+            #[inline(always)]
+            fn from_lanes(values: &[f64]) -> $t {
+                let mut lanes = [0 as $lane; $lanes];
+                for (lane, &value) in lanes.iter_mut().zip(values) {
+                    *lane = value as $lane;
+                }
+                $t::from_slice_unaligned(&lanes)
+            }
+            #[inline(always)]
+            fn extract(self, lane: usize) -> f64 {
+                $t::extract(self, lane) as f64
+            }
+            #[inline(always)]
+            fn replace(self, lane: usize, value: f64) -> $t {
+                $t::replace(self, lane, value as $lane)
+            }
+            #[inline(always)]
+            fn greater(self, other: $t) -> $m {
+                self.gt(other)
+            }
+            #[inline(always)]
+            fn less(self, other: $t) -> $m {
+                self.lt(other)
+            }
+            #[inline(always)]
+            fn equal(self, other: $t) -> $m {
+                self.eq(other)
+            }
+            #[inline(always)]
+            fn select(mask: $m, yes: $t, no: $t) -> $t {
+                mask.select(yes, no)
+            }
+            #[inline(always)]
+            fn mask(value: bool) -> $m {
+                $m::splat(value)
+            }
+            #[inline(always)]
+            fn test(mask: $m, lane: usize) -> bool {
+                mask.extract(lane)
+            }
+            #[inline(always)]
+            fn all(mask: $m) -> bool {
+                mask.all()
+            }
+            #[inline(always)]
+            fn any(mask: $m) -> bool {
+                mask.any()
+            }
+        }
+    };
+}
+
+// 2^12 + 1 for the 24 bits of f32, 2^27 + 1 for the 53 of f64
+scalar_float!(f32, 4097.0);
+scalar_float!(f64, 134_217_729.0);
+vector_float!(f32x8, m32x8, f32, 8);
+vector_float!(f32x16, m32x16, f32, 16);
+vector_float!(f64x2, m64x2, f64, 2);
+vector_float!(f64x4, m64x4, f64, 4);
+vector_float!(f64x8, m64x8, f64, 8);
+
+/// Numbers `z` is iterated in: a [`Float`], or a [`DoubleDouble`] of one.
+pub trait Real: Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Send + Sync {
+    /// The plain lanes the bailout and the counts are computed in.
+    type Float: Float;
+    fn from_f64(value: f64) -> Self;
+    /// Nearest plain value.
+    fn approx(self) -> Self::Float;
+    /// Lanes that are exactly equal.
+    fn identical(self, other: Self) -> Mask<Self>;
+    #[inline(always)]
+    fn square(self) -> Self {
+        self * self
+    }
+}
+
+/// Mask of the lanes of a [`Real`].
+pub type Mask<R> = <<R as Real>::Float as Float>::Mask;
+
+impl<T: Float> Real for T {
+    type Float = T;
+    #[inline(always)]
+    fn from_f64(value: f64) -> T {
+        T::splat(value)
+    }
+    #[inline(always)]
+    fn approx(self) -> T {
+        self
+    }
+    #[inline(always)]
+    fn identical(self, other: T) -> T::Mask {
+        self.equal(other)
+    }
+}
+
+impl<T: Float> Real for DoubleDouble<T> {
+    type Float = T;
+    #[inline]
+    fn from_f64(value: f64) -> DoubleDouble<T> {
+        DoubleDouble::splat(value)
+    }
+    #[inline]
+    fn approx(self) -> T {
+        self.hi
+    }
+    #[inline]
+    fn identical(self, other: DoubleDouble<T>) -> T::Mask {
+        self.hi.equal(other.hi) & self.lo.equal(other.lo)
+    }
+    #[inline]
+    fn square(self) -> DoubleDouble<T> {
+        DoubleDouble::square(self)
+    }
+}
+
+/// One step of `z = z^2 + c`.
+#[inline(always)]
+pub fn next_point<R: Real>(z: Complex<R>, c: Complex<R>) -> Complex<R> {
+    let product = z.re * z.im;
+    Complex {
+        re: z.re.square() - z.im.square() + c.re,
+        im: product + product + c.im,
+    }
+}
+
+/// Lanes in the main cardioid or the period-2 bulb, which never escape.
+#[inline(always)]
+pub fn in_main_bulbs<R: Real>(c: Complex<R>) -> Mask<R> {
+    let y2 = c.im.square();
+    let x = c.re - R::from_f64(0.25);
+    let q = x.square() + y2;
+    let cardioid = (q * (q + x)).approx().less((R::from_f64(0.25) * y2).approx());
+    let x = c.re + R::from_f64(1.0);
+    cardioid | (x.square() + y2).approx().less(R::Float::splat(0.0625))
+}
+
+/// Iterates every lane of `c` from `z = 0` and returns its escape count with |z|^2
+/// taken `extra` iterations after the escape.
+///
+/// The count is the 0-based index of the iteration after which `|z| > 2`, or `limit`
+/// for a lane that stays bounded; `single::escapes` spells out the contract. With
+/// `shortcuts`, interior lanes stop early: the main cardioid and the period-2 bulb are
+/// rejected up front, and an orbit that lands exactly on a saved point is periodic and
+/// can never escape (Brent's cycle detection, the saved point moves on after intervals
+/// of 1, 2, 4, ... iterations). The counts are the same either way.
+///
+/// Escaped lanes keep iterating with the others, their results are frozen.
+#[inline]
+pub fn iterate<R: Real>(c: Complex<R>, limit: u64, extra: u64, shortcuts: bool) -> (R::Float, R::Float) {
+    let limit_lanes = R::Float::splat(limit as f64);
+    let extra_lanes = R::Float::splat(extra as f64);
+    let four = R::Float::splat(4.0);
+    let mut count = limit_lanes;
+    let mut norm = R::Float::splat(0.0);
+    let mut active = R::Float::mask(true);
+    if shortcuts {
+        active = !in_main_bulbs(c);
+        if !R::Float::any(active) {
+            return (count, norm);
+        }
+    }
+    let mut sampled = R::Float::mask(false);
+    let mut z = Complex { re: R::from_f64(0.0), im: R::from_f64(0.0) };
+    let mut saved = z;
+    let (mut interval, mut steps) = (1u64, 0u64);
+    for i in 0..limit + extra {
+        z = next_point(z, c);
+        let (re, im) = (z.re.approx(), z.im.approx());
+        let sum = re * re + im * im;
+        let index = R::Float::splat(i as f64);
+        if i < limit {
+            let escaped = active & sum.greater(four);
+            count = R::Float::select(escaped, index, count);
+            active = active & !escaped;
+            if shortcuts {
+                active = active & !(z.re.identical(saved.re) & z.im.identical(saved.im));
+                steps += 1;
+                if steps == interval {
+                    saved = z;
+                    steps = 0;
+                    interval *= 2;
+                }
+            }
+        }
+        // lanes that never escape keep count = limit and are never sampled
+        let sample = index.equal(count + extra_lanes);
+        norm = R::Float::select(sample, sum, norm);
+        sampled = sampled | sample;
+
+        let bounded = count.equal(limit_lanes) & (!active | R::Float::mask(i + 1 >= limit));
+        if R::Float::all(sampled | bounded) {
+            break;
+        }
+    }
+    (count, norm)
+}
+
+/// Escape counts of the lanes of `c`, see [`iterate`].
+#[inline]
+pub fn escapes<R: Real>(c: Complex<R>, limit: u64, shortcuts: bool) -> R::Float {
+    iterate(c, limit, 0, shortcuts).0
+}
+
+/// Smooth counts (see [`smooth_count`]) of the first `out.len()` lanes of `c`. Lanes
+/// that never escape are `limit`.
+#[inline]
+pub fn escapes_smooth<R: Real>(c: Complex<R>, limit: u64, shortcuts: bool, out: &mut [f32]) {
+    let (count, norm) = iterate(c, limit, crate::renderer::SMOOTH_EXTRA, shortcuts);
+    for (lane, value) in out.iter_mut().enumerate() {
+        let n = count.extract(lane) as u64;
+        *value = if n == limit { limit as f32 } else { smooth_count(n, norm.extract(lane)) };
+    }
+}
+
+/// Most lanes of any [`Float`].
+const MAX_LANES: usize = 16;
+
+/// [`iterate`] over the `len` points `point(0..len)`, `F::LANES` at a time, keeping every
+/// lane busy: a lane that is done hands its count and |z|^2 to `store(index, count, norm)`
+/// and takes the next point, so one slow pixel does not hold up the others. Same counts
+/// as `iterate`, `norm` means nothing for counts of `limit`.
+///
+/// Always inlined, with everything it calls, so that the `#[target_feature]` wrappers
+/// of `simd::Isa` get their own copy compiled for their instruction set.
+#[inline(always)]
+pub fn escapes_refill<F, P, S>(len: usize, point: P, limit: u64, extra: u64, shortcuts: bool, mut store: S)
+    where F: Float, P: Fn(usize) -> Complex<f64>, S: FnMut(usize, u64, f64)
+{
+    let (zero, one) = (F::splat(0.0), F::splat(1.0));
+    let limit_lanes = F::splat(limit as f64);
+    let extra_lanes = F::splat(extra as f64);
+    let four = F::splat(4.0);
+    let mut c = Complex { re: zero, im: zero };
+    let (mut z, mut saved) = (c, c);
+    let (mut iteration, mut count, mut norm) = (zero, limit_lanes, zero);
+    let (mut steps, mut interval) = (zero, one);
+    let (mut active, mut sampled) = (F::mask(false), F::mask(false));
+    // the point each lane works on, `None` once the queue is empty
+    let mut pixels = [None; MAX_LANES];
+    let mut next = 0;
+    let mut done = F::mask(true);
+    loop {
+        if F::any(done) {
+            for (lane, pixel) in pixels.iter_mut().enumerate().take(F::LANES) {
+                if !F::test(done, lane) {
+                    continue;
+                }
+                if let Some(index) = *pixel {
+                    store(index, count.extract(lane) as u64, norm.extract(lane));
+                }
+                let p = if next < len {
+                    *pixel = Some(next);
+                    next += 1;
+                    point(next - 1)
+                } else {
+                    *pixel = None;
+                    Complex::new(0.0, 0.0)
+                };
+                c = Complex { re: c.re.replace(lane, p.re), im: c.im.replace(lane, p.im) };
+            }
+            if pixels.iter().all(Option::is_none) {
+                return;
+            }
+            z = Complex { re: F::select(done, zero, z.re), im: F::select(done, zero, z.im) };
+            saved = Complex { re: F::select(done, zero, saved.re), im: F::select(done, zero, saved.im) };
+            iteration = F::select(done, zero, iteration);
+            count = F::select(done, limit_lanes, count);
+            norm = F::select(done, zero, norm);
+            steps = F::select(done, zero, steps);
+            interval = F::select(done, one, interval);
+            let start = if shortcuts { !in_main_bulbs(c) } else { F::mask(true) };
+            active = (active & !done) | (done & start);
+            sampled = sampled & !done;
+        }
+
+        z = next_point(z, c);
+        let sum = z.re * z.re + z.im * z.im;
+        let counting = iteration.less(limit_lanes);
+        let escaped = active & counting & sum.greater(four);
+        count = F::select(escaped, iteration, count);
+        active = active & !escaped;
+        if shortcuts {
+            active = active & !(counting & z.re.equal(saved.re) & z.im.equal(saved.im));
+            steps = F::select(counting, steps + one, steps);
+            let advance = counting & steps.equal(interval);
+            saved = Complex { re: F::select(advance, z.re, saved.re), im: F::select(advance, z.im, saved.im) };
+            steps = F::select(advance, zero, steps);
+            interval = F::select(advance, interval + interval, interval);
+        }
+        // lanes that never escape keep count = limit and are never sampled
+        let sample = iteration.equal(count + extra_lanes);
+        norm = F::select(sample, sum, norm);
+        sampled = sampled | sample;
+
+        let last = !(iteration + one).less(limit_lanes);
+        done = sampled | (count.equal(limit_lanes) & (!active | last));
+        iteration = iteration + one;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lanes_match_scalar() {
+        let re = [-0.75, 0.3, -1.25, 0.0, -0.1, 0.26, -2.0, 1.0];
+        let im = [0.1, -0.5, 0.02, 1.0, 0.1, 0.0, 0.0, 0.0];
+        let limit = 300;
+        let lanes = Complex { re: f64x8::from_lanes(&re), im: f64x8::from_lanes(&im) };
+        let narrow = Complex { re: f32x8::from_lanes(&re), im: f32x8::from_lanes(&im) };
+        let zero = f64x8::splat(0.0);
+        let dd = Complex {
+            re: DoubleDouble { hi: f64x8::from_lanes(&re), lo: zero },
+            im: DoubleDouble { hi: f64x8::from_lanes(&im), lo: zero },
+        };
+        for &shortcuts in &[false, true] {
+            let counts = escapes(lanes, limit, shortcuts);
+            let narrow_counts = escapes(narrow, limit, shortcuts);
+            let dd_counts = escapes(dd, limit, shortcuts);
+            let mut smooth = [0f32; 8];
+            escapes_smooth(lanes, limit, shortcuts, &mut smooth);
+            for lane in 0..8 {
+                let c = Complex::new(re[lane], im[lane]);
+                let c32 = Complex::new(re[lane] as f32, im[lane] as f32);
+                let c_dd = Complex { re: DoubleDouble::from(re[lane]), im: DoubleDouble::from(im[lane]) };
+                assert_eq!(counts.extract(lane), escapes(c, limit, shortcuts), "{}", c);
+                assert_eq!(narrow_counts.extract(lane), escapes(c32, limit, shortcuts) as f64, "{}", c);
+                assert_eq!(dd_counts.extract(lane), escapes(c_dd, limit, shortcuts), "{}", c);
+                let mut one = [0f32];
+                escapes_smooth(c, limit, shortcuts, &mut one);
+                assert_eq!(smooth[lane], one[0], "{}", c);
+            }
+        }
+    }
+
+    #[test]
+    fn refill_matches_escapes() {
+        // interior points next to fast escapes, more of them than lanes
+        let points: Vec<_> = (0..37)
+            .map(|i| Complex::new(-2.1 + 0.07 * i as f64, 0.4 * ((i * 7) % 5) as f64 - 0.8))
+            .collect();
+        let limit = 300;
+        for &shortcuts in &[false, true] {
+            for &extra in &[0, crate::renderer::SMOOTH_EXTRA] {
+                let mut wide = vec![(0, 0.0); points.len()];
+                escapes_refill::<f64x4, _, _>(points.len(), |i| points[i], limit, extra, shortcuts, |i, count, norm| wide[i] = (count, norm));
+                let mut narrow = vec![(0, 0.0); points.len()];
+                escapes_refill::<f32x8, _, _>(points.len(), |i| points[i], limit, extra, shortcuts, |i, count, norm| narrow[i] = (count, norm));
+                for (i, &c) in points.iter().enumerate() {
+                    let (count, norm) = iterate(c, limit, extra, shortcuts);
+                    assert_eq!(wide[i].0, count as u64, "{}", c);
+                    let c32 = Complex::new(c.re as f32, c.im as f32);
+                    assert_eq!(narrow[i].0, escapes(c32, limit, shortcuts) as u64, "{}", c);
+                    if wide[i].0 < limit {
+                        assert_eq!(wide[i].1, norm, "{}", c);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,21 +1,18 @@
 //! The simplest possible example that does something.
-mod constants;
-mod opencl;
-mod renderer;
-mod simd;
-mod single;
-mod multi;
-
 use rgsl;
 use ggez;
 use ggez::event;
 use ggez::graphics;
 use ggez::timer;
 use ggez::nalgebra as na;
-use ggez::{conf::*, Context, GameResult, mint,  graphics::*, event::*};
-use constants::*;
+use ggez::{conf::*, Context, GameError, GameResult, mint,  graphics::*, event::*};
 use rgsl::{Spline, InterpAccel};
-use renderer::*;
+use ggez_mandel::constants::*;
+use ggez_mandel::*;
+use ggez_mandel::fixed;
+use ggez_mandel::registry::Precision;
+use num::Complex;
+use std::sync::Arc;
 
 
 struct Splines {
@@ -27,11 +24,92 @@ struct Splines {
     ba: InterpAccel,
 }
 
-struct Renderers {
-    opencl: Box<dyn MandelbrotRenderer>,
-    simd: Box<dyn MandelbrotRenderer>,
-    single: Box<dyn MandelbrotRenderer>,
-    multi: Box<dyn MandelbrotRenderer>
+struct NamedRenderer {
+    name: &'static str,
+    precision: Precision,
+    renderer: Arc<dyn MandelbrotRenderer>,
+}
+
+// недоступный бэкенд (например, без OpenCL) просто пропускаем
+fn load_backends(registry: &Registry) -> Vec<NamedRenderer> {
+    registry.entries()
+        .iter()
+        .filter_map(|entry| match entry.create() {
+            Ok(renderer) => Some(NamedRenderer { name: entry.name, precision: entry.capabilities.precision, renderer: Arc::from(renderer) }),
+            Err(e) => {
+                println!("{} renderer unavailable: {}", entry.name, e);
+                None
+            }
+        })
+        .collect()
+}
+
+// самый мелкий пиксель, который ещё отличим от соседних (для центра порядка 1)
+fn smallest_pixel(precision: Precision) -> f64 {
+    match precision {
+        Precision::F32 => 1e-6,
+        Precision::F64 => 1e-13,
+        Precision::DoubleFloat => 1e-12,
+        Precision::DoubleDouble => 1e-29,
+        Precision::Perturbation => 0.0,
+    }
+}
+
+fn list_renderers(registry: &Registry) {
+    for entry in registry.entries() {
+        let status = match entry.availability() {
+            Ok(()) => "available".to_string(),
+            Err(e) => format!("unavailable ({})", e),
+        };
+        let caps = &entry.capabilities;
+        println!(
+            "{:8} {:?}, max limit {}, formulas {:?}: {}",
+            entry.name, caps.precision, caps.max_limit, caps.formulas, status
+        );
+    }
+}
+
+impl Splines {
+    // count может быть дробным (плавная раскраска), палитра интерполируется сплайнами
+    fn get_color(&mut self, count: &Option<f64>, limit: f64) -> [u8; 4]  {
+        let xi = match count {
+            None => 0.0,
+            Some(count) => 1 as f64 - (*count/limit),
+        };
+        [
+            self.r.eval(xi, &mut self.ra) as u8,
+            self.g.eval(xi, &mut self.ga) as u8,
+            self.b.eval(xi, &mut self.ba) as u8,
+            255
+        ]
+    }
+    // пишем цвета в переиспользуемый буфер, без новой аллокации на кадр
+    fn colorize(&mut self, counts: &[f32], limit: f64, rgba: &mut Vec<u8>) {
+        rgba.resize(counts.len() * 4, 0);
+        for (item, pixel) in counts.iter().zip(rgba.chunks_mut(4)) {
+            let wrapped = if *item < (limit-1.0) as f32 {
+                Some(*item as f64)
+            } else {
+                None
+            };
+            pixel.copy_from_slice(&self.get_color(&wrapped, limit));
+        }
+    }
+}
+
+fn show(ctx: &mut Context, viewport: &Viewport, rgba: &[u8]) -> GameResult {
+    if rgba.len() != viewport.width * viewport.height * 4 {
+        return Ok(());
+    }
+    let fractal = graphics::Image::from_rgba8(
+        ctx,
+        viewport.width as u16,
+        viewport.height as u16,
+        rgba
+    )?;
+    let scale: mint::Vector2<f32> = mint::Vector2 { x: 1.0, y: 1.0};
+    let point: na::Point2<f32> = na::Point2::new(0.0, 0.0);
+    graphics::draw(ctx, &fractal, DrawParam::default().scale(scale).dest(point))
 }
 
 fn get_splines() -> Splines {
@@ -88,56 +166,59 @@ fn get_splines() -> Splines {
 
 struct MainState {
     fractal_buffer: Vec<u8>,
+    // область, для которой посчитан fractal_buffer
+    fractal_viewport: Viewport,
     fractal_rendered: bool,
+    worker: RenderWorker,
+    // id последнего отправленного задания; кадры старых заданий игнорируем
+    job: u64,
+    rendering: bool,
     splines: Splines,
-    zoom: f64,
+    viewport: Viewport,
     limit: f64,
-    center_x: f64,
-    center_y: f64,
-    cur_renderer: u8,
-    renderers: Renderers
+    smooth: bool,
+    cur_renderer: usize,
+    renderers: Vec<NamedRenderer>,
+    // точность последнего кадра, если бэкенд выбирает её сам
+    precision: Option<Precision>,
 }
 
 impl MainState {
-    fn new() -> GameResult<MainState> {
+    fn new(registry: &Registry, renderer_name: &str, location: Option<Location>) -> GameResult<MainState> {
         let initial_buffer = Vec::with_capacity((WINDOW_WIDTH as usize * WINDOW_HEIGHT as usize * 4) as usize);
         let dims = (WINDOW_WIDTH as usize, WINDOW_HEIGHT as usize);
+        let renderers = load_backends(registry);
+        let cur_renderer = renderers.iter().position(|backend| backend.name == renderer_name).ok_or_else(|| {
+            let available: Vec<_> = renderers.iter().map(|backend| backend.name).collect();
+            GameError::ConfigError(format!("renderer '{}' is not available, one of: {}", renderer_name, available.join(", ")))
+        })?;
+        let location = location.unwrap_or_else(|| Location {
+            center: Complex {
+                re: FRACTAL_CENTER_X.parse().unwrap(),
+                im: -FRACTAL_CENTER_Y.parse::<Fixed>().unwrap(),
+            },
+            scale: ZOOM,
+            rotation: 0.0,
+            limit: LIMIT as usize,
+        });
+        let viewport = location.viewport(dims);
         let s = MainState {
             fractal_buffer: initial_buffer,
+            fractal_viewport: viewport,
             fractal_rendered: false,
+            worker: RenderWorker::spawn(),
+            job: 0,
+            rendering: false,
             splines:  get_splines(),
-            zoom: ZOOM, limit: LIMIT,
-            center_x: FRACTAL_CENTER_X,
-            center_y: 0. - FRACTAL_CENTER_Y,
-            cur_renderer: 1,
-            renderers: Renderers{
-                opencl: Box::new(opencl::OCLMandelbrot::new(dims)),
-                simd: Box::new(simd::SIMDMandelbrot::new(dims)),
-                single: Box::new(single::SingleMandelbrot::new(dims)),
-                multi: Box::new(multi::MultiMandelbrot::new(dims))
-            }
+            viewport,
+            limit: location.limit as f64,
+            smooth: true,
+            cur_renderer,
+            renderers,
+            precision: None,
         };
         Ok(s)
     }
-    fn get_color(&mut self, count: &Option<usize>) -> Vec<u8>  {
-        match count {
-            None => vec![
-                self.splines.r.eval(0.0, &mut self.splines.ra) as u8,
-                self.splines.g.eval(0.0, &mut self.splines.ga) as u8,
-                self.splines.b.eval(0.0, &mut self.splines.ba) as u8,
-                255
-            ].clone(),
-            Some(count) => {
-                let xi = 1 as f64 - (*count as f64/self.limit as f64);
-                vec![
-                    self.splines.r.eval(xi, &mut self.splines.ra) as u8,
-                    self.splines.g.eval(xi,  &mut self.splines.ga) as u8,
-                    self.splines.b.eval(xi, &mut self.splines.ba) as u8,
-                    255
-                ].clone()
-            }
-        }
-    }
 }
 
 impl event::EventHandler for MainState {
@@ -146,69 +227,89 @@ impl event::EventHandler for MainState {
             println!("Delta frame time: {:?} ", timer::delta(ctx));
             println!("Average FPS: {}", timer::fps(ctx));
         }
+
+        // переасчитываем множество только если надо, в фоновом потоке
+        if !self.fractal_rendered {
+            let backend = &self.renderers[self.cur_renderer];
+            let precision = backend.renderer.precision(&self.viewport, self.limit as usize);
+            if let Some(used) = precision.filter(|_| precision != self.precision) {
+                println!("{} renders in {:?}", backend.name, used);
+            }
+            self.precision = precision;
+            self.job = self.worker.submit(RenderJob {
+                renderer: self.renderers[self.cur_renderer].renderer.clone(),
+                viewport: self.viewport,
+                limit: self.limit as usize,
+                smooth: self.smooth,
+            }).map_err(|e| GameError::RenderError(e.to_string()))?;
+            self.rendering = true;
+            self.fractal_rendered = true;
+        }
+
+        // забираем готовые проходы: сначала грубый, потом уточнения
+        while let Some(event) = self.worker.try_recv() {
+            match event {
+                RenderEvent::Pass { job, viewport, values } if job == self.job => {
+                    self.splines.colorize(&values, self.limit, &mut self.fractal_buffer);
+                    self.fractal_viewport = viewport;
+                }
+                RenderEvent::Done { job, result } if job == self.job => {
+                    self.rendering = false;
+                    // при ошибке оставляем последний выведенный кадр
+                    if let Err(e) = result {
+                        println!("render failed: {}", e);
+                    }
+                }
+                _ => {}
+            }
+        }
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
         // очищаем
         graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
-        // расчитываем переменные
-        let ratio = WINDOW_WIDTH as f64 / WINDOW_HEIGHT as f64;
-        let zoom = self.zoom;
-        let width = zoom /2.0;
-        let height = zoom /2.0/ratio;
-        let iterations = self.limit;
 
-        // переасчитываем множество только если надо
-        if !self.fractal_rendered {
-            let xr = std::ops::Range{start: self.center_x - width, end: self.center_x + width};
-            let yr = std::ops::Range{start: self.center_y - height, end: self.center_y + height};
-            // выбираем способ расчета
-            let renderer = match self.cur_renderer{
-                0 => &self.renderers.opencl,
-                1 => &self.renderers.simd,
-                2 => &self.renderers.single,
-                _ => &self.renderers.multi,
-            };
-
-            let buffer= renderer.render(xr, yr, self.limit as usize).unwrap()
-                .iter()
-                .flat_map(|item| {
-                    let wrapped = if *item < (iterations-1.0) as u64{
-                        Some(*item as usize)
-                    } else {
-                        None
-                    };
-                    self.get_color(&wrapped)
-                })
-                .collect::<Vec<u8>>();
-
-            self.fractal_buffer = buffer;
+        // вывод изображения: последний готовый кадр, пока считается новый
+        show(ctx, &self.fractal_viewport, &self.fractal_buffer)?;
+        if self.rendering {
+            let text = graphics::Text::new("rendering\u{2026}");
+            let point: na::Point2<f32> = na::Point2::new(10.0, 10.0);
+            graphics::draw(ctx, &text, DrawParam::default().dest(point))?;
         }
-        self.fractal_rendered = true;
-
-        // вывод изображения
-        let fractal = graphics::Image::from_rgba8(
-            ctx,
-            WINDOW_WIDTH as u16,
-            WINDOW_HEIGHT as u16,
-            &self.fractal_buffer
-        ).unwrap();
-        let scale: mint::Vector2<f32> = mint::Vector2 { x: 1.0, y: 1.0};
-        let point: na::Point2<f32> = na::Point2::new(0.0, 0.0);
-        graphics::draw(ctx, &fractal, DrawParam::default().scale(scale).dest(point))?;
 
         graphics::present(ctx)?;
         Ok(())
     }
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        // масштаб (ширина в комплексной плоскости) сохраняется, меняется только сетка пикселей
+        graphics::set_screen_coordinates(ctx, Rect::new(0.0, 0.0, width, height)).unwrap();
+        self.viewport.width = width as usize;
+        self.viewport.height = height as usize;
+        self.fractal_rendered = false;
+    }
     fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+        // сдвиг на 10% ширины окна, в целых пикселях: тогда воркер
+        // сдвигает прошлый кадр и досчитывает только открывшиеся полосы
+        let step = (self.viewport.width / 10) as f64;
         if keycode == KeyCode::Z {
-            self.zoom += 0.05 * self.zoom;
+            self.viewport.scale += 0.05 * self.viewport.scale;
             self.fractal_rendered = false;
         }
-        if keycode == KeyCode::X {
-            self.zoom -= 0.05 * self.zoom;
+        // глубже не пускают ни f64-масштаб, ни разрядность Fixed в координатах центра
+        let deepest = self.viewport.scale <= MIN_ZOOM || !fixed::resolves(0.95 * self.viewport.pixel_size());
+        if keycode == KeyCode::X && deepest {
+            println!("this is the deepest zoom the view coordinates hold");
+        }
+        if keycode == KeyCode::X && !deepest {
+            self.viewport.scale -= 0.05 * self.viewport.scale;
             self.fractal_rendered = false;
+            // дальше соседние пиксели для этой точности сливаются
+            let backend = &self.renderers[self.cur_renderer];
+            let precision = backend.renderer.precision(&self.viewport, self.limit as usize).unwrap_or(backend.precision);
+            if self.viewport.pixel_size() < smallest_pixel(precision) {
+                println!("{} runs out of precision at this zoom, switch to 'dd-simd' or 'perturbation' with R", backend.name);
+            }
         }
         if keycode == KeyCode::C {
             self.limit += 0.5 * self.limit;
@@ -219,23 +320,44 @@ impl event::EventHandler for MainState {
             self.fractal_rendered = false;
         }
         if keycode == KeyCode::A {
-            self.center_x -= 0.1 * self.zoom;
+            self.viewport.pan(-step, 0.0);
             self.fractal_rendered = false;
         }
         if keycode == KeyCode::D {
-            self.center_x += 0.1 * self.zoom;
+            self.viewport.pan(step, 0.0);
             self.fractal_rendered = false;
         }
         if keycode == KeyCode::W {
-            self.center_y -= 0.1 * self.zoom;
+            self.viewport.pan(0.0, -step);
             self.fractal_rendered = false;
         }
         if keycode == KeyCode::S {
-            self.center_y += 0.1 * self.zoom;
+            self.viewport.pan(0.0, step);
             self.fractal_rendered = false;
         }
+        if keycode == KeyCode::Q {
+            self.viewport.rotation -= std::f64::consts::PI / 36.0;
+            self.fractal_rendered = false;
+        }
+        if keycode == KeyCode::E {
+            self.viewport.rotation += std::f64::consts::PI / 36.0;
+            self.fractal_rendered = false;
+        }
+        if keycode == KeyCode::B {
+            // плавная раскраска / целые итерации
+            self.smooth = !self.smooth;
+            self.fractal_rendered = false;
+        }
+        if keycode == KeyCode::L {
+            // точка сохраняется со всеми знаками центра
+            match Location::of(&self.viewport, self.limit as usize).save(LOCATION_FILE) {
+                Ok(()) => println!("location saved to {}", LOCATION_FILE),
+                Err(e) => println!("could not save location: {}", e),
+            }
+        }
         if keycode == KeyCode::R {
-            self.cur_renderer = (1 + self.cur_renderer) % 4;
+            self.cur_renderer = (1 + self.cur_renderer) % self.renderers.len();
+            println!("renderer: {}", self.renderers[self.cur_renderer].name);
             self.fractal_rendered = false;
         }
     }
@@ -243,13 +365,53 @@ impl event::EventHandler for MainState {
 
 
 pub fn main() -> GameResult {
+    // --renderer <name> выбирает бэкенд, --list-renderers печатает доступные,
+    // --location <file> открывает сохранённую точку, --threads <n> ограничивает число
+    // потоков CPU-бэкендов
+    let mut registry = Registry::default();
+    let mut renderer_name = DEFAULT_RENDERER.to_string();
+    let mut location = None;
+    let mut list = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--renderer" => match args.next() {
+                Some(name) => renderer_name = name,
+                None => println!("--renderer needs a name, one of: {}", registry.names().collect::<Vec<_>>().join(", ")),
+            },
+            "--location" => match args.next().map(Location::load) {
+                Some(Ok(loaded)) => location = Some(loaded),
+                Some(Err(e)) => println!("could not load location: {}", e),
+                None => println!("--location needs a file, as written by the L key"),
+            },
+            "--threads" => match args.next().and_then(|n| n.parse().ok()) {
+                // все бэкенды реестра считают на общем пуле из стольких потоков
+                Some(threads) => match Registry::with_threads(threads) {
+                    Ok(pooled) => registry = pooled,
+                    Err(e) => println!("could not set up {} threads: {}", threads, e),
+                },
+                None => println!("--threads needs a number of threads"),
+            },
+            "--list-renderers" => list = true,
+            _ => println!("unknown argument {}", arg),
+        }
+    }
+    if list {
+        list_renderers(&registry);
+        return Ok(());
+    }
+    if registry.get(&renderer_name).is_none() {
+        let names = registry.names().collect::<Vec<_>>().join(", ");
+        return Err(GameError::ConfigError(format!("unknown renderer '{}', one of: {}", renderer_name, names)));
+    }
+
     let app_config = ggez::conf::Conf {
         window_mode: WindowMode {
             width: WINDOW_WIDTH as f32,
             height: WINDOW_HEIGHT as f32,
             borderless: false,
             fullscreen_type: FullscreenType::Windowed,
-            resizable: false,
+            resizable: true,
             maximized: false,
             ..WindowMode::default()
         },
@@ -265,7 +427,7 @@ pub fn main() -> GameResult {
     };
     let cb = ggez::ContextBuilder::new("mandelbrot", "ggez").conf(app_config);
     let (ctx, event_loop) = &mut cb.build()?;
-    let state = &mut MainState::new()?;
+    let state = &mut MainState::new(&registry, &renderer_name, location)?;
     event::run(ctx, event_loop, state)
 }
 
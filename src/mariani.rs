@@ -0,0 +1,239 @@
+use crate::error::RenderError;
+use crate::renderer::{MandelbrotRenderer, RenderParams, CancelToken, Count, Counts, check_tile, check_limit};
+use crate::single::SingleMandelbrot;
+use crate::viewport::{Viewport, Tile};
+use num::Complex;
+use rayon::prelude::*;
+
+/// Rectangles with at most this many samples inside their border are rendered outright.
+/// Lines are rendered in pieces of this many samples.
+const LEAF_SAMPLES: usize = 256;
+
+/// Mariani–Silver subdivision, level by level on every core.
+///
+/// Only rectangle borders are iterated. A rectangle whose border has a single value is
+/// filled with it, any other one is split in two across its longer side, down to leaf
+/// blocks that are rendered whole. The set and the bands of equal count have no holes,
+/// so the fill is right unless a detail slips between two border samples or the
+/// rectangle surrounds the whole set. Rectangles around c = 0 are never filled.
+///
+/// The new border lines of all rectangles of a level go through the single-threaded
+/// kernel in one parallel pass, and so do all leaves at the end. The rectangles of a
+/// level only ever write inside themselves, so the result is that of splitting them
+/// one at a time.
+pub struct MarianiSilver {
+    inner: SingleMandelbrot,
+}
+
+/// Samples of the tile being rendered, border included.
+#[derive(Copy, Clone, Debug)]
+struct Rect {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+/// What becomes of a rectangle whose border is rendered.
+enum Split {
+    /// Filled, or no samples inside.
+    Done,
+    /// Its inside is to be rendered.
+    Leaf(Rect),
+    /// The line across it is to be rendered, then the two halves that share it split.
+    Halves(Rect, [Rect; 2]),
+}
+
+struct Subdivision<'a, T, K> {
+    tile: &'a Tile,
+    out: &'a mut [T],
+    kernel: K,
+    cancel: &'a CancelToken,
+    /// Position of c = 0 in tile samples.
+    origin: (f64, f64),
+}
+
+impl<'a, T, K> Subdivision<'a, T, K>
+    where T: Copy + Default + PartialEq + Send + Sync,
+          K: Fn(&Tile, &mut [T]) -> Result<(), RenderError> + Sync
+{
+    /// Iterates every sample of `rects`, in pieces of at most `LEAF_SAMPLES` samples in
+    /// parallel.
+    fn render(&mut self, rects: &[Rect]) -> Result<(), RenderError> {
+        let mut pieces = Vec::new();
+        for &rect in rects {
+            if rect.height == 1 {
+                for x in (rect.x..rect.x + rect.width).step_by(LEAF_SAMPLES) {
+                    pieces.push(Rect { x, width: LEAF_SAMPLES.min(rect.x + rect.width - x), ..rect });
+                }
+            } else {
+                let rows = (LEAF_SAMPLES / rect.width).max(1);
+                for y in (rect.y..rect.y + rect.height).step_by(rows) {
+                    pieces.push(Rect { y, height: rows.min(rect.y + rect.height - y), ..rect });
+                }
+            }
+        }
+
+        let (tile, kernel, cancel) = (self.tile, &self.kernel, self.cancel);
+        let step = tile.step;
+        let rendered = pieces.par_iter()
+            .map(|piece| {
+                cancel.check()?;
+                let piece_tile = Tile {
+                    x: tile.x + piece.x * step,
+                    y: tile.y + piece.y * step,
+                    width: piece.width,
+                    height: piece.height,
+                    step,
+                };
+                let mut values = vec![T::default(); piece_tile.len()];
+                kernel(&piece_tile, &mut values)?;
+                Ok(values)
+            })
+            .collect::<Result<Vec<_>, RenderError>>()?;
+
+        for (piece, values) in pieces.iter().zip(rendered) {
+            for (j, row) in values.chunks(piece.width).enumerate() {
+                let start = (piece.y + j) * tile.width + piece.x;
+                self.out[start..start + piece.width].copy_from_slice(row);
+            }
+        }
+        Ok(())
+    }
+
+    fn uniform_border(&self, rect: Rect) -> Option<T> {
+        let at = |x: usize, y: usize| self.out[y * self.tile.width + x];
+        let (right, bottom) = (rect.x + rect.width - 1, rect.y + rect.height - 1);
+        let value = at(rect.x, rect.y);
+        let rows = (rect.x..=right).all(|x| at(x, rect.y) == value && at(x, bottom) == value);
+        let columns = (rect.y..=bottom).all(|y| at(rect.x, y) == value && at(right, y) == value);
+        if rows && columns { Some(value) } else { None }
+    }
+
+    fn surrounds_origin(&self, rect: Rect) -> bool {
+        let (x, y) = self.origin;
+        x >= rect.x as f64 && x <= (rect.x + rect.width - 1) as f64
+            && y >= rect.y as f64 && y <= (rect.y + rect.height - 1) as f64
+    }
+
+    /// Fills in `rect`, whose border is rendered already, or tells what to render next.
+    fn split(&mut self, rect: Rect) -> Split {
+        if rect.width <= 2 || rect.height <= 2 {
+            return Split::Done;
+        }
+        let inside = Rect { x: rect.x + 1, y: rect.y + 1, width: rect.width - 2, height: rect.height - 2 };
+
+        if let Some(value) = self.uniform_border(rect) {
+            if !self.surrounds_origin(rect) {
+                for y in inside.y..inside.y + inside.height {
+                    let start = y * self.tile.width + inside.x;
+                    for sample in &mut self.out[start..start + inside.width] {
+                        *sample = value;
+                    }
+                }
+                return Split::Done;
+            }
+        }
+        if inside.width * inside.height <= LEAF_SAMPLES {
+            return Split::Leaf(inside);
+        }
+
+        // the new border line is shared by both halves
+        if rect.width >= rect.height {
+            let mid = rect.x + rect.width / 2;
+            Split::Halves(Rect { x: mid, width: 1, ..inside }, [
+                Rect { width: mid - rect.x + 1, ..rect },
+                Rect { x: mid, width: rect.x + rect.width - mid, ..rect },
+            ])
+        } else {
+            let mid = rect.y + rect.height / 2;
+            Split::Halves(Rect { y: mid, height: 1, ..inside }, [
+                Rect { height: mid - rect.y + 1, ..rect },
+                Rect { y: mid, height: rect.y + rect.height - mid, ..rect },
+            ])
+        }
+    }
+
+    /// Fills in `full`, whose border is rendered already.
+    fn subdivide(&mut self, full: Rect) -> Result<(), RenderError> {
+        let mut level = vec![full];
+        let mut leaves = Vec::new();
+        while !level.is_empty() {
+            self.cancel.check()?;
+            let mut lines = Vec::new();
+            let mut next = Vec::new();
+            for rect in level {
+                match self.split(rect) {
+                    Split::Done => {}
+                    Split::Leaf(inside) => leaves.push(inside),
+                    Split::Halves(line, halves) => {
+                        lines.push(line);
+                        next.extend_from_slice(&halves);
+                    }
+                }
+            }
+            self.render(&lines)?;
+            level = next;
+        }
+        self.render(&leaves)
+    }
+}
+
+impl MarianiSilver {
+    fn subdivide<T, K>(viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [T], kernel: K) -> Result<(), RenderError>
+        where T: Copy + Default + PartialEq + Send + Sync,
+              K: Fn(&Tile, &mut [T]) -> Result<(), RenderError> + Sync
+    {
+        check_tile(viewport, tile, out.len())?;
+        let (x, y) = viewport.complex_to_pixel(Complex::new(0.0, 0.0));
+        let step = tile.step as f64;
+        let mut subdivision = Subdivision {
+            tile,
+            out,
+            kernel,
+            cancel: &params.cancel,
+            origin: ((x - tile.x as f64) / step, (y - tile.y as f64) / step),
+        };
+
+        let (width, height) = (tile.width, tile.height);
+        let full = Rect { x: 0, y: 0, width, height };
+        let mut border = vec![Rect { height: 1, ..full }];
+        if height > 1 {
+            border.push(Rect { y: height - 1, height: 1, ..full });
+        }
+        if height > 2 {
+            border.push(Rect { y: 1, width: 1, height: height - 2, ..full });
+            if width > 1 {
+                border.push(Rect { x: width - 1, y: 1, width: 1, height: height - 2 });
+            }
+        }
+        subdivision.render(&border)?;
+        subdivision.subdivide(full)
+    }
+
+    fn counts<C: Count>(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [C]) -> Result<(), RenderError> {
+        check_limit::<C>(params.limit)?;
+        Self::subdivide(viewport, tile, params, out, |tile, out: &mut [C]| {
+            self.inner.render_tile(viewport, tile, params, C::counts(out))
+        })
+    }
+}
+
+impl MandelbrotRenderer for MarianiSilver {
+    fn new() -> Result<MarianiSilver, RenderError> {
+        Ok(MarianiSilver { inner: SingleMandelbrot::new()? })
+    }
+    fn render_tile(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: Counts) -> Result<(), RenderError> {
+        match out {
+            Counts::U16(out) => self.counts(viewport, tile, params, out),
+            Counts::U32(out) => self.counts(viewport, tile, params, out),
+            Counts::F32(out) => self.counts(viewport, tile, params, out),
+        }
+    }
+    /// Smooth values are only equal inside the set, so only the interior is filled.
+    fn render_tile_smooth(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [f32]) -> Result<(), RenderError> {
+        Self::subdivide(viewport, tile, params, out, |tile, out: &mut [f32]| {
+            self.inner.render_tile_smooth(viewport, tile, params, out)
+        })
+    }
+}
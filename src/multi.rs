@@ -1,47 +1,59 @@
 
-use crate::renderer::{MandelbrotRenderer};
+use crate::renderer::{MandelbrotRenderer, RenderParams, Count, Counts, check_tile, check_limit};
+use crate::schedule::{self, probe_cost};
+use crate::single::{escapes_fast, escapes_smooth_fast};
+use crate::error::RenderError;
+use crate::viewport::{Viewport, Tile};
 use num::Complex;
-use std::error::Error;
-use rayon::prelude::*;
 
-pub struct MultiMandelbrot{
-    dims: (usize, usize)
-}
+/// `SingleMandelbrot` on every core: the view is split into blocks that are rendered
+/// most expensive first, see [`schedule::by_cost`].
+pub struct MultiMandelbrot;
 
 
-#[inline]
-fn escapes(c: Complex<f64>, limit: u64) -> u64 {
-    let mut z = Complex { re: 0.0, im: 0.0 };
-    for i in 0..limit {
-        z = z*z + c;
-        if z.norm_sqr() > 4.0 {
-            return i;
-        }
+impl MultiMandelbrot {
+    fn map_pixels<T, F>(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [T], f: F) -> Result<(), RenderError>
+        where T: Send, F: Fn(Complex<f64>) -> T + Sync + Send
+    {
+        check_tile(viewport, tile, out.len())?;
+        let map = viewport.pixel_map();
+        let limit = params.limit as u64;
+        let cost = |x: usize, y: usize| probe_cost(map.at(x as f64, y as f64), limit);
+        schedule::by_cost(tile, out, &params.cancel, cost, |block, rows| {
+            for (j, row) in rows.iter_mut().enumerate() {
+                params.cancel.check()?;
+                for (i, value) in row.iter_mut().enumerate() {
+                    let (x, y) = block.pixel(i, j);
+                    *value = f(map.at(x as f64, y as f64));
+                }
+            }
+            Ok(())
+        })
     }
-    limit
-}
 
+    fn counts<C: Count>(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [C]) -> Result<(), RenderError> {
+        check_limit::<C>(params.limit)?;
+        let limit = params.limit as u64;
+        self.map_pixels(viewport, tile, params, out, |point| C::from_count(escapes_fast(point, limit)))
+    }
+}
 
 impl MandelbrotRenderer for MultiMandelbrot {
-    fn new(dims: (usize, usize)) -> MultiMandelbrot {
-        MultiMandelbrot { dims }
+    fn new() -> Result<MultiMandelbrot, RenderError> {
+        Ok(MultiMandelbrot)
+    }
+    fn render_tile(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: Counts) -> Result<(), RenderError> {
+        match out {
+            Counts::U16(out) => self.counts(viewport, tile, params, out),
+            Counts::U32(out) => self.counts(viewport, tile, params, out),
+            Counts::F32(out) => self.counts(viewport, tile, params, out),
+        }
+    }
+    fn render_tile_smooth(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [f32]) -> Result<(), RenderError> {
+        let limit = params.limit as u64;
+        self.map_pixels(viewport, tile, params, out, |point| escapes_smooth_fast(point, limit))
     }
-    fn render(&self, xr: std::ops::Range<f64>, yr: std::ops::Range<f64>, limit: usize) -> Result<Vec<u64>, Box<dyn Error>> {
-        let (width, height) = self.dims;
-        let colors = (0..(width * height) as usize)
-            .into_par_iter()
-            .map(|idx| {
-                let x = idx % (width as usize) ;
-                let y = idx / (width as usize);
-                let dx = (xr.end - xr.start) / (width as f64);
-                let dy = (yr.end - yr.start) / (height as f64);
-                let point = Complex { re: xr.start + x as f64 * dx, im: yr.start + y as f64 * dy };
-                escapes(
-                    point,
-                    limit as u64,
-                )
-            })
-            .collect::<Vec<u64>>();
-        Ok(colors)
+    fn conjugate_symmetric(&self, _viewport: &Viewport) -> bool {
+        true
     }
 }
@@ -1,18 +1,7 @@
 
-mod lib;
-mod constants;
-mod opencl;
-mod single;
-mod renderer;
-mod multi;
-mod simd;
-
-
-use single::SingleMandelbrot;
-use multi::MultiMandelbrot;
-use simd::SIMDMandelbrot;
-use opencl::OCLMandelbrot;
-use renderer::MandelbrotRenderer;
+use ggez_mandel::{MandelbrotRenderer, Viewport, SingleMandelbrot, MultiMandelbrot, SIMDMandelbrot, OCLMandelbrot, MarianiSilver, DDSIMDMandelbrot};
+use ggez_mandel::simd::Isa;
+use num::Complex;
 use criterion::{criterion_group, criterion_main, Criterion, Fun};
 
 fn compare_escapes(c: &mut Criterion) {
@@ -21,21 +10,86 @@ fn compare_escapes(c: &mut Criterion) {
     let limit2 = limit.clone();
     let limit3 = limit.clone();
     let limit4 = limit.clone();
-    let renderer_single= SingleMandelbrot::new(dims);
-    let renderer_opencl= OCLMandelbrot::new(dims);
-    let renderer_multi= MultiMandelbrot::new(dims);
-    let renderer_simd= SIMDMandelbrot::new(dims);
-    let mand_single = Fun::new("single", move |b, _i| b.iter(|| renderer_single.render(std::ops::Range{start: -1.0, end: 0.5}, std::ops::Range{start: -1.0, end: 1.0}, limit3)));
-    let mand_multi = Fun::new("multi", move |b, _i| b.iter(|| renderer_multi.render(std::ops::Range{start: -1.0, end: 0.5}, std::ops::Range{start: -1.0, end: 1.0}, limit2)));
-    let mand_opencl = Fun::new("opencl", move |b, _i| b.iter(|| renderer_opencl.render(std::ops::Range{start: -1.0, end: 0.5}, std::ops::Range{start: -1.0, end: 1.0}, limit)));
-    let mand_simd = Fun::new("simd", move |b, _i| b.iter(|| renderer_simd.render(std::ops::Range{start: -1.0, end: 0.5}, std::ops::Range{start: -1.0, end: 1.0}, limit4)));
+    let viewport = Viewport::new(Complex::new(-0.25, 0.0), 1.5, dims);
+    let renderer_single= SingleMandelbrot::new().unwrap();
+    let renderer_multi= MultiMandelbrot::new().unwrap();
+    let renderer_simd= SIMDMandelbrot::new().unwrap();
+    let renderer_simd_f32= SIMDMandelbrot::narrow();
+    let renderer_mariani= MarianiSilver::new().unwrap();
+    let renderer_dd_simd= DDSIMDMandelbrot::new().unwrap();
+    let mand_single = Fun::new("single", move |b, _i| b.iter(|| renderer_single.render(&viewport, limit3)));
+    let mand_multi = Fun::new("multi", move |b, _i| b.iter(|| renderer_multi.render(&viewport, limit2)));
+    let mand_simd = Fun::new("simd", move |b, _i| b.iter(|| renderer_simd.render(&viewport, limit4)));
+    let mand_simd_f32 = Fun::new("simd-f32", move |b, _i| b.iter(|| renderer_simd_f32.render(&viewport, limit)));
+    let mand_mariani = Fun::new("mariani", move |b, _i| b.iter(|| renderer_mariani.render(&viewport, limit)));
+    let mand_dd_simd = Fun::new("dd-simd", move |b, _i| b.iter(|| renderer_dd_simd.render(&viewport, limit)));
 
 
-    let functions = vec![mand_single, mand_multi, mand_simd, mand_opencl];
+    let mut functions = vec![mand_single, mand_multi, mand_simd, mand_simd_f32, mand_mariani, mand_dd_simd];
+    match OCLMandelbrot::new() {
+        Ok(renderer_opencl) => {
+            let mand_opencl = Fun::new("opencl", move |b, _i| b.iter(|| renderer_opencl.render(&viewport, limit)));
+            functions.push(mand_opencl);
+        }
+        Err(e) => println!("skipping opencl: {}", e),
+    }
 
     c.bench_functions("Mandelbrot", functions, 10);
 }
 
+/// Seahorse valley: slow pixels next to fast ones in most SIMD blocks, where lanes
+/// that wait for their neighbours waste the most.
+fn compare_boundary(c: &mut Criterion) {
+    let limit = 2000;
+    let viewport = Viewport::new(Complex::new(-0.7436, 0.1318), 0.01, (500, 500));
+    let renderer_multi = MultiMandelbrot::new().unwrap();
+    let renderer_simd = SIMDMandelbrot::new().unwrap();
+    let renderer_dd_simd = DDSIMDMandelbrot::new().unwrap();
+    let functions = vec![
+        Fun::new("multi", move |b, _i| b.iter(|| renderer_multi.render(&viewport, limit))),
+        Fun::new("simd", move |b, _i| b.iter(|| renderer_simd.render(&viewport, limit))),
+        // still waits for the slowest of its 8 lanes
+        Fun::new("dd-simd", move |b, _i| b.iter(|| renderer_dd_simd.render(&viewport, limit))),
+    ];
+    c.bench_functions("Mandelbrot boundary", functions, 10);
+}
+
+/// The period-3 bulb filling most of the view: interior pixels iterate to the limit
+/// unless Mariani–Silver fills them in from their borders.
+fn compare_interior(c: &mut Criterion) {
+    let limit = 2000;
+    let viewport = Viewport::new(Complex::new(-0.1225, 0.7449), 0.12, (500, 500));
+    let renderer_multi = MultiMandelbrot::new().unwrap();
+    let renderer_mariani = MarianiSilver::new().unwrap();
+    let functions = vec![
+        Fun::new("multi", move |b, _i| b.iter(|| renderer_multi.render(&viewport, limit))),
+        Fun::new("mariani", move |b, _i| b.iter(|| renderer_mariani.render(&viewport, limit))),
+    ];
+    c.bench_functions("Mandelbrot interior", functions, 10);
+}
+
+/// The SIMD kernels of every instruction set this CPU has, on the same view. Wider ones
+/// only win if the dispatch really runs code compiled for them.
+fn compare_isa(c: &mut Criterion) {
+    let limit = 500;
+    let viewport = Viewport::new(Complex::new(-0.25, 0.0), 1.5, (500, 500));
+    let mut functions = Vec::new();
+    for &isa in Isa::ALL.iter() {
+        if !isa.is_supported() {
+            println!("skipping {:?}, not on this CPU", isa);
+            continue;
+        }
+        // only AVX2 and AVX-512 have f32 lanes
+        let narrow: &[bool] = if isa == Isa::Avx512 || isa == Isa::Avx2 { &[false, true] } else { &[false] };
+        for &narrow in narrow {
+            let renderer = SIMDMandelbrot::with_isa(isa, narrow).unwrap();
+            let name = format!("{:?}{}", isa, if narrow { " f32" } else { "" });
+            functions.push(Fun::new(&name, move |b, _i| b.iter(|| renderer.render(&viewport, limit))));
+        }
+    }
+    c.bench_functions("Mandelbrot isa", functions, 10);
+}
+
 //pub fn criterion_benchmark(c: &mut Criterion) {
 //
 //    c.bench_function("escapes", |b| b.iter(|| test_escape()));
@@ -48,6 +102,6 @@ fn setup() -> Criterion {
 criterion_group! {
     name = benches;
     config = setup();
-    targets = compare_escapes
+    targets = compare_escapes, compare_boundary, compare_interior, compare_isa
 }
 criterion_main!(benches);
\ No newline at end of file
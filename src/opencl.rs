@@ -1,97 +1,392 @@
 extern crate ocl;
 
-use ocl::ProQue;
+use ocl::{ProQue, OclPrm};
 use ocl::{SpatialDims, Device, Platform, Buffer};
-use super::renderer::{MandelbrotRenderer};
-use std::error::Error;
+use ocl::enums::{DeviceInfo, DeviceInfoResult};
+use ocl::prm::Float2;
+use super::renderer::{MandelbrotRenderer, RenderParams, Count, Counts, check_tile, check_limit, f32_resolves, SMOOTH_EXTRA};
+use super::error::RenderError;
+use super::registry::Precision;
+use super::viewport::{Viewport, Tile};
+use std::sync::Mutex;
 
+/// GPU renderer. The kernels come in three precisions: f32, f64 on devices with
+/// `cl_khr_fp64`, and double-float (pairs of f32, see [`Precision::DoubleFloat`]) for
+/// devices without it. Unless one is forced with `with_precision`, every view gets the
+/// fastest one that still tells its pixels apart, see [`OCLMandelbrot::precision_for`].
 pub struct OCLMandelbrot{
     queue: ProQue,
-    buffer: Buffer<u64>,
-    dims: (usize, usize)
+    fp64: bool,
+    forced: Option<Precision>,
+    // grow to the largest image rendered so far and are reused for smaller ones,
+    // one per output type
+    counts_u16: Mutex<Option<Buffer<u16>>>,
+    counts_u32: Mutex<Option<Buffer<u32>>>,
+    counts_f32: Mutex<Option<Buffer<f32>>>,
+    smooth: Mutex<Option<Buffer<f32>>>,
 }
-impl MandelbrotRenderer for OCLMandelbrot {
-    fn new(dims: (usize, usize)) -> OCLMandelbrot {
-        let platform = Platform::default();
-        let device = Device::first(platform).unwrap();
-        let src = r#"
-         #pragma OPENCL EXTENSION cl_khr_fp64 : enable
+
+/// Rows per kernel enqueue; the cancel token is checked between bands.
+const BAND_ROWS: usize = 64;
+
+/// Pieces of the program every precision shares.
+const COMMON: &str = r#"
+        // two_sum and friends need every operation rounded on its own
+        #pragma OPENCL FP_CONTRACT OFF
+
         int index(int x, int y, int width) {
           return width*y + x;
         }
-        #pragma OPENCL EXTENSION cl_khr_fp64 : enable
-        __kernel void render(__global size_t *out, float x_s, float x_e, float y_s, float y_e,  int limit) {
-          int x_dim = get_global_id(0);
-          int y_dim = get_global_id(1);
-          size_t width = get_global_size(0);
-          size_t height = get_global_size(1);
-          int idx = index(x_dim, y_dim, width);
 
-          float dx = (x_e - x_s) / width;
-          float dy = (y_e - y_s) / height;
+        #define PLAIN_ARITHMETIC(T, P)                                      \
+        T add_##P(T a, T b) { return a + b; }                               \
+        T sub_##P(T a, T b) { return a - b; }                               \
+        T mul_##P(T a, T b) { return a * b; }                               \
+        T real_##P(float a) { return a; }                                   \
+        T from_int_##P(int a) { return a; }                                 \
+        T approx_##P(T a) { return a; }                                     \
+        bool eq_##P(T a, T b) { return a == b; }
+
+        PLAIN_ARITHMETIC(float, f32)
+
+        // double-float, the f32 version of dd.rs: .x is the rounded value, .y the
+        // rest. Products are exact through fma.
+        float2 two_sum(float a, float b) {
+          float s = a + b;
+          float v = s - a;
+          return (float2)(s, (a - (s - v)) + (b - v));
+        }
+
+        float2 quick_two_sum(float a, float b) {
+          float s = a + b;
+          return (float2)(s, b - (s - a));
+        }
+
+        float2 add_df(float2 a, float2 b) {
+          float2 high = two_sum(a.x, b.x);
+          float2 low = two_sum(a.y, b.y);
+          float2 sum = quick_two_sum(high.x, high.y + low.x);
+          return quick_two_sum(sum.x, sum.y + low.y);
+        }
+
+        float2 sub_df(float2 a, float2 b) {
+          return add_df(a, -b);
+        }
+
+        float2 mul_df(float2 a, float2 b) {
+          float hi = a.x * b.x;
+          float lo = fma(a.x, b.x, -hi);
+          return quick_two_sum(hi, lo + (a.x * b.y + a.y * b.x));
+        }
+
+        float2 real_df(float a) {
+          return (float2)(a, 0.0f);
+        }
+
+        float2 from_int_df(int a) {
+          float hi = a;
+          return (float2)(hi, (float)(a - (int)hi));
+        }
+
+        float approx_df(float2 a) {
+          return a.x;
+        }
+
+        bool eq_df(float2 a, float2 b) {
+          return a.x == b.x && a.y == b.y;
+        }
+"#;
 
-          float x_origin = x_s + dx * x_dim;
-          float y_origin = y_s + dy * y_dim;
+/// Only built on devices with `cl_khr_fp64`.
+const FP64: &str = r#"
+        #pragma OPENCL EXTENSION cl_khr_fp64 : enable
+        PLAIN_ARITHMETIC(double, f64)
+"#;
 
-          float x = 0.0;
-          float y = 0.0;
+/// The kernels, instantiated once per precision by `source`: `$REAL` is the number type
+/// of the orbit, `$LOW` the type the bailout and the smooth count are computed in, and
+/// `$P` ends the names of the arithmetic and of the kernels.
+const KERNELS: &str = r#"
+        // main cardioid and period-2 bulb, as in kernel::in_main_bulbs
+        bool in_main_bulbs_$P($REAL x_origin, $REAL y_origin) {
+          $REAL y2 = mul_$P(y_origin, y_origin);
+          $REAL x = sub_$P(x_origin, real_$P(0.25f));
+          $REAL q = add_$P(mul_$P(x, x), y2);
+          if(approx_$P(mul_$P(q, add_$P(q, x))) < approx_$P(mul_$P(real_$P(0.25f), y2))) {
+            return true;
+          }
+          x = add_$P(x_origin, real_$P(1.0f));
+          return approx_$P(add_$P(mul_$P(x, x), y2)) < 0.0625f;
+        }
 
-          int iteration = 0;
+        // same escape count as single::escapes: the 0-based iteration that left
+        // the radius 2 circle, or limit if the orbit never did. On escape the orbit
+        // runs `extra` more iterations and leaves |z|^2 in *norm for the smooth count.
+        // Interior points stop early as in kernel::iterate.
+        int escape_$P($REAL x_origin, $REAL y_origin, int limit, int extra, $LOW *norm) {
+          if(in_main_bulbs_$P(x_origin, y_origin)) {
+            return limit;
+          }
+          $REAL x = real_$P(0.0f);
+          $REAL y = real_$P(0.0f);
+          $REAL saved_x = x;
+          $REAL saved_y = y;
+          int interval = 1;
+          int steps = 0;
 
-          int max_iteration = limit;
-          while(x*x + y*y <= 4 && iteration < max_iteration) {
-            float xtemp = x*x - y*y + x_origin;
-            y = 2*x*y + y_origin;
+          for(int iteration = 0; iteration < limit; iteration++) {
+            $REAL xy = mul_$P(x, y);
+            $REAL xtemp = add_$P(sub_$P(mul_$P(x, x), mul_$P(y, y)), x_origin);
+            y = add_$P(add_$P(xy, xy), y_origin);
             x = xtemp;
-            iteration++;
+            $LOW x_low = approx_$P(x);
+            $LOW y_low = approx_$P(y);
+            if(x_low*x_low + y_low*y_low > 4) {
+              for(int i = 0; i < extra; i++) {
+                xy = mul_$P(x, y);
+                xtemp = add_$P(sub_$P(mul_$P(x, x), mul_$P(y, y)), x_origin);
+                y = add_$P(add_$P(xy, xy), y_origin);
+                x = xtemp;
+              }
+              x_low = approx_$P(x);
+              y_low = approx_$P(y);
+              *norm = x_low*x_low + y_low*y_low;
+              return iteration;
+            }
+            if(eq_$P(x, saved_x) && eq_$P(y, saved_y)) {
+              return limit;
+            }
+            if(++steps == interval) {
+              saved_x = x;
+              saved_y = y;
+              steps = 0;
+              interval *= 2;
+            }
           }
+          return limit;
+        }
+
+        // sample of the tile -> viewport pixel as in Tile::pixel, then the same
+        // mapping as PixelMap::at
+        void origin_$P($REAL o_re, $REAL o_im,
+                       $REAL dx_re, $REAL dx_im,
+                       $REAL dy_re, $REAL dy_im,
+                       int x0, int y0, int step,
+                       $REAL *x_origin, $REAL *y_origin) {
+          $REAL x_dim = from_int_$P(x0 + get_global_id(0) * step);
+          $REAL y_dim = from_int_$P(y0 + get_global_id(1) * step);
+          *x_origin = add_$P(add_$P(o_re, mul_$P(x_dim, dx_re)), mul_$P(y_dim, dy_re));
+          *y_origin = add_$P(add_$P(o_im, mul_$P(x_dim, dx_im)), mul_$P(y_dim, dy_im));
+        }
 
-          if(iteration == max_iteration) {
-            out[idx] = max_iteration;
+        // one counts kernel per output type, see Counts
+        #define RENDER_COUNTS_$P(name, T)                                   \
+        __kernel void name(__global T *out,                                 \
+                           $REAL o_re, $REAL o_im,                          \
+                           $REAL dx_re, $REAL dx_im,                        \
+                           $REAL dy_re, $REAL dy_im,                        \
+                           int x0, int y0, int step,                        \
+                           int limit, int extra) {                          \
+          size_t width = get_global_size(0);                                \
+          int idx = index(get_global_id(0), get_global_id(1), width);       \
+          $REAL x_origin, y_origin;                                         \
+          origin_$P(o_re, o_im, dx_re, dx_im, dy_re, dy_im,                 \
+                    x0, y0, step, &x_origin, &y_origin);                    \
+          $LOW norm;                                                        \
+          out[idx] = (T)escape_$P(x_origin, y_origin, limit, 0, &norm);     \
+        }
+
+        RENDER_COUNTS_$P(render_u16_$P, ushort)
+        RENDER_COUNTS_$P(render_u32_$P, uint)
+        RENDER_COUNTS_$P(render_f32_$P, float)
+
+        // smooth_count from renderer.rs
+        __kernel void render_smooth_$P(__global float *out,
+                                       $REAL o_re, $REAL o_im,
+                                       $REAL dx_re, $REAL dx_im,
+                                       $REAL dy_re, $REAL dy_im,
+                                       int x0, int y0, int step,
+                                       int limit, int extra) {
+          size_t width = get_global_size(0);
+          int idx = index(get_global_id(0), get_global_id(1), width);
+          $REAL x_origin, y_origin;
+          origin_$P(o_re, o_im, dx_re, dx_im, dy_re, dy_im,
+                    x0, y0, step, &x_origin, &y_origin);
+
+          $LOW norm;
+          int count = escape_$P(x_origin, y_origin, limit, extra, &norm);
+          if(count == limit) {
+            out[idx] = limit;
           } else {
-            out[idx] = iteration;
+            float log_z = 0.5f * log2((float)norm);
+            out[idx] = fmax((float)(count + extra) - log2(log_z), 0.0f);
           }
         }
-    "#;
+"#;
+
+/// Suffix of the kernels of `precision`.
+fn suffix(precision: Precision) -> &'static str {
+    match precision {
+        Precision::F64 => "f64",
+        Precision::DoubleFloat => "df",
+        _ => "f32",
+    }
+}
 
+/// The whole program, with the f64 kernels only if the device has them.
+fn source(fp64: bool) -> String {
+    let mut src = COMMON.to_string();
+    let mut instances = vec![(Precision::F32, "float", "float"), (Precision::DoubleFloat, "float2", "float")];
+    if fp64 {
+        src.push_str(FP64);
+        instances.push((Precision::F64, "double", "double"));
+    }
+    for (precision, real, low) in instances {
+        src.push_str(&KERNELS.replace("$REAL", real).replace("$LOW", low).replace("$P", suffix(precision)));
+    }
+    src
+}
+
+/// `value` as an unevaluated sum of two f32.
+fn double_float(value: f64) -> Float2 {
+    let hi = value as f32;
+    Float2::new(hi, (value - hi as f64) as f32)
+}
 
+impl OCLMandelbrot {
+    /// Builds the kernels on the first device, iterating every view in `precision`, or
+    /// in the one `precision_for` picks for it when `None`. F64 needs `cl_khr_fp64`.
+    pub fn with_precision(precision: Option<Precision>) -> Result<OCLMandelbrot, RenderError> {
+        // Platform::default() panics when no ICD is installed, so ask the loader directly
+        let platform = ocl::core::get_platform_ids().ok()
+            .and_then(|ids| ids.into_iter().next())
+            .map(Platform::new)
+            .ok_or(RenderError::NoPlatform)?;
+        let device = Device::first(platform).map_err(|_| RenderError::NoPlatform)?;
+        let fp64 = match device.info(DeviceInfo::Extensions) {
+            Ok(DeviceInfoResult::Extensions(extensions)) => extensions.split_whitespace().any(|ext| ext == "cl_khr_fp64"),
+            _ => false,
+        };
+        match precision {
+            None | Some(Precision::F32) | Some(Precision::DoubleFloat) => {}
+            Some(Precision::F64) if fp64 => {}
+            Some(precision) => return Err(RenderError::UnsupportedPrecision(precision)),
+        }
 
         let pro_que = ProQue::builder()
             .platform(platform)
             .device(device)
-            .src(src)
-            .dims(dims.0*dims.1)
-            .build().unwrap();
+            .src(source(fp64))
+            .build()
+            .map_err(|e| RenderError::KernelCompile(e.to_string()))?;
 //        dbg!(pro_que.device().name());
-        let buffer = pro_que.create_buffer::<u64>().unwrap();
-        OCLMandelbrot{
+        Ok(OCLMandelbrot{
             queue: pro_que,
-            dims,
-            buffer
+            fp64,
+            forced: precision,
+            counts_u16: Mutex::new(None),
+            counts_u32: Mutex::new(None),
+            counts_f32: Mutex::new(None),
+            smooth: Mutex::new(None),
+        })
+    }
+
+    /// The arithmetic `viewport` is rendered in: f32 while it tells the pixels apart,
+    /// past that f64, or double-float when the device has no f64.
+    pub fn precision_for(&self, viewport: &Viewport) -> Precision {
+        if let Some(precision) = self.forced {
+            return precision;
         }
+        if f32_resolves(viewport) {
+            Precision::F32
+        } else if self.fp64 {
+            Precision::F64
+        } else {
+            Precision::DoubleFloat
+        }
+    }
+
+    fn counts<C: Count + OclPrm>(&self, kernel_name: &str, slot: &Mutex<Option<Buffer<C>>>, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [C]) -> Result<(), RenderError> {
+        check_limit::<C>(params.limit)?;
+        self.run(kernel_name, slot, viewport, tile, params, out)
     }
-    fn render(&self, xr: std::ops::Range<f64>, yr: std::ops::Range<f64>, limit: usize) ->Result<Vec<u64>, Box<dyn Error>> {
-        //println!("xr=({},{}), yr=({},{}), limit={}", xr.start, xr.end, yr.start, yr.end, limit);
-        let mut kernel = self.queue.kernel_builder("render")
-            .arg(&self.buffer)
-            .arg(xr.start as f32)
-            .arg(xr.end as f32)
-            .arg(yr.start as f32)
-            .arg(yr.end as f32)
-            .arg(limit as i32)
-            .build().expect("cant render");
 
-        kernel.set_default_global_work_size(SpatialDims::Two(self.dims.0,self.dims.1));
+    fn run<T: OclPrm>(&self, kernel_name: &str, slot: &Mutex<Option<Buffer<T>>>, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [T]) -> Result<(), RenderError> {
+        check_tile(viewport, tile, out.len())?;
+        // the kernels take the limit as an int
+        if params.limit > i32::MAX as usize {
+            return Err(RenderError::LimitTooLarge { limit: params.limit, max: i32::MAX as u64 });
+        }
+        let (width, height) = (tile.width, tile.height);
+        let len = width * height;
+        let map = viewport.pixel_map();
+        let precision = self.precision_for(viewport);
+
+        let mut buffer = slot.lock()
+            .map_err(|_| RenderError::Poisoned("opencl buffer"))?;
+        if buffer.as_ref().map_or(true, |b| b.len() < len) {
+            *buffer = Some(self.queue.buffer_builder::<T>().len(len).build()?);
+        }
+        let buffer = buffer.as_ref().unwrap();
 
-        unsafe { kernel.enq().expect("cant render"); }
+        let mut builder = self.queue.kernel_builder(format!("{}_{}", kernel_name, suffix(precision)));
+        builder.arg(buffer);
+        for &value in &[map.origin.re, map.origin.im, map.dx.re, map.dx.im, map.dy.re, map.dy.im] {
+            match precision {
+                Precision::F64 => builder.arg(value),
+                Precision::DoubleFloat => builder.arg(double_float(value)),
+                _ => builder.arg(value as f32),
+            };
+        }
+        let kernel = builder
+            .arg(tile.x as i32)
+            .arg(tile.y as i32)
+            .arg(tile.step as i32)
+            .arg(params.limit as i32)
+            .arg(SMOOTH_EXTRA as i32)
+            .build()?;
 
-        let mut vec = vec![0u64; self.buffer.len()];
-        self.buffer.read(&mut vec).enq().expect("cant render");
+        // the work offset keeps get_global_id(1) the row within the whole tile
+        for row in (0..height).step_by(BAND_ROWS) {
+            params.cancel.check()?;
+            let rows = BAND_ROWS.min(height - row);
+            unsafe {
+                kernel.cmd()
+                    .global_work_offset(SpatialDims::Two(0, row))
+                    .global_work_size(SpatialDims::Two(width, rows))
+                    .enq()?;
+            }
+            self.queue.queue().finish()?;
+        }
 
-        Ok(vec)
+        buffer.read(out).len(len).enq()?;
+        Ok(())
     }
 }
 
+impl MandelbrotRenderer for OCLMandelbrot {
+    fn new() -> Result<OCLMandelbrot, RenderError> {
+        OCLMandelbrot::with_precision(None)
+    }
+    fn render_tile(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: Counts) -> Result<(), RenderError> {
+        match out {
+            Counts::U16(out) => self.counts("render_u16", &self.counts_u16, viewport, tile, params, out),
+            Counts::U32(out) => self.counts("render_u32", &self.counts_u32, viewport, tile, params, out),
+            Counts::F32(out) => self.counts("render_f32", &self.counts_f32, viewport, tile, params, out),
+        }
+    }
+    fn render_tile_smooth(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [f32]) -> Result<(), RenderError> {
+        self.run("render_smooth", &self.smooth, viewport, tile, params, out)
+    }
+    fn precision(&self, viewport: &Viewport, _limit: usize) -> Option<Precision> {
+        Some(self.precision_for(viewport))
+    }
+    fn conjugate_symmetric(&self, viewport: &Viewport) -> bool {
+        // the f64 kernels map pixels exactly like PixelMap::at, the narrower ones round
+        // the map first
+        self.precision_for(viewport) == Precision::F64
+    }
+}
 
 
 //#[cfg(test)]
@@ -105,4 +400,4 @@ impl MandelbrotRenderer for OCLMandelbrot {
 //        let yr = std::ops::Range{start: -1., end: -2.};
 //        assert_eq!(renderer.generate(dims, xr, yr, 100).expect("error"), vec![1,1]);
 //    }
-//}
\ No newline at end of file
+//}
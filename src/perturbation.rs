@@ -0,0 +1,215 @@
+use crate::error::RenderError;
+use crate::fixed::Fixed;
+use crate::floatexp::FloatExp;
+use crate::renderer::{MandelbrotRenderer, RenderParams, Count, Counts, check_tile, check_limit, smooth_count, SMOOTH_EXTRA};
+use crate::viewport::{Viewport, Tile};
+use num::Complex;
+use rayon::prelude::*;
+use std::ops::{Add, Mul, Sub};
+use std::sync::{Arc, Mutex};
+
+/// Pixels smaller than this iterate their deltas in [`FloatExp`], the deltas of smaller
+/// pixels shrink past the range of f64 while they follow the reference.
+const FLOATEXP_BELOW: f64 = 1e-290;
+
+/// Deep zoom renderer built on perturbation theory.
+///
+/// One reference orbit `Z` is iterated at the view center in [`Fixed`] point and every
+/// pixel `c = C + dc` only follows its difference from it, `d' = (2Z + d) d + dc`, which
+/// stays small enough for f64. A pixel whose orbit gets closer to 0 than to the
+/// reference (where the delta loses its digits), or that outlives a reference which
+/// escaped, is rebased: its full `z` becomes the delta against the start of the orbit.
+///
+/// The scale of the view is still an f64, which ends zooms at about 1e-300.
+pub struct PerturbationMandelbrot {
+    /// Orbit of the last view, shared by its tiles and passes.
+    orbit: Mutex<Option<Arc<Orbit>>>,
+}
+
+struct Orbit {
+    center: Complex<Fixed>,
+    limit: usize,
+    /// `Z_0 = 0` to the escape or the limit, rounded to f64.
+    points: Vec<Complex<f64>>,
+}
+
+impl Orbit {
+    fn new(center: Complex<Fixed>, limit: usize) -> Orbit {
+        let mut z = Complex { re: Fixed::ZERO, im: Fixed::ZERO };
+        let mut points = Vec::with_capacity(limit + 1);
+        points.push(Complex::new(0.0, 0.0));
+        for _ in 0..limit {
+            let product = z.re * z.im;
+            z = Complex {
+                re: z.re.square() - z.im.square() + center.re,
+                im: product + product + center.im,
+            };
+            let point = Complex::new(z.re.to_f64(), z.im.to_f64());
+            points.push(point);
+            if point.norm_sqr() > 4.0 {
+                break;
+            }
+        }
+        Orbit { center, limit, points }
+    }
+
+    /// Iterates the pixel `C + dc` and returns its escape count with its `z` at the
+    /// escape, or `limit` with zero.
+    fn escapes<D: Delta>(&self, dc: Complex<f64>, limit: u64) -> (u64, Complex<f64>) {
+        let (dc_re, dc_im) = (D::from_f64(dc.re), D::from_f64(dc.im));
+        let (mut d_re, mut d_im) = (D::from_f64(0.0), D::from_f64(0.0));
+        let mut m = 0;
+        for i in 0..limit {
+            let (z_re, z_im) = (D::from_f64(self.points[m].re), D::from_f64(self.points[m].im));
+            let (a_re, a_im) = (z_re + z_re + d_re, z_im + z_im + d_im);
+            let re = a_re * d_re - a_im * d_im + dc_re;
+            d_im = a_re * d_im + a_im * d_re + dc_im;
+            d_re = re;
+            m += 1;
+
+            let (z_re, z_im) = (D::from_f64(self.points[m].re) + d_re, D::from_f64(self.points[m].im) + d_im);
+            let z = Complex::new(z_re.to_f64(), z_im.to_f64());
+            if z.norm_sqr() > 4.0 {
+                return (i, z);
+            }
+            // in max norm and in `D`: the squares of deltas below 1e-154 underflow f64
+            if max_norm(z_re, z_im) < max_norm(d_re, d_im) || m == self.points.len() - 1 {
+                d_re = z_re;
+                d_im = z_im;
+                m = 0;
+            }
+        }
+        (limit, Complex::new(0.0, 0.0))
+    }
+}
+
+/// `max(|re|, |im|)`.
+#[inline]
+fn max_norm<D: Delta>(re: D, im: D) -> D {
+    let (re, im) = (re.abs(), im.abs());
+    if re < im { im } else { re }
+}
+
+/// Arithmetic the deltas are iterated in.
+trait Delta: Copy + PartialOrd + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> {
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn abs(self) -> Self;
+}
+
+impl Delta for f64 {
+    #[inline]
+    fn from_f64(value: f64) -> f64 {
+        value
+    }
+    #[inline]
+    fn to_f64(self) -> f64 {
+        self
+    }
+    #[inline]
+    fn abs(self) -> f64 {
+        f64::abs(self)
+    }
+}
+
+impl Delta for FloatExp {
+    #[inline]
+    fn from_f64(value: f64) -> FloatExp {
+        FloatExp::from_f64(value)
+    }
+    #[inline]
+    fn to_f64(self) -> f64 {
+        FloatExp::to_f64(self)
+    }
+    #[inline]
+    fn abs(self) -> FloatExp {
+        FloatExp::abs(self)
+    }
+}
+
+impl PerturbationMandelbrot {
+    fn orbit(&self, viewport: &Viewport, limit: usize) -> Result<Arc<Orbit>, RenderError> {
+        let mut cached = self.orbit.lock()
+            .map_err(|_| RenderError::Poisoned("reference orbit"))?;
+        match &*cached {
+            Some(orbit) if orbit.center == viewport.precise_center && orbit.limit == limit => Ok(orbit.clone()),
+            _ => {
+                let orbit = Arc::new(Orbit::new(viewport.precise_center, limit));
+                *cached = Some(orbit.clone());
+                Ok(orbit)
+            }
+        }
+    }
+
+    /// Calls `f` with the reference orbit and the offset from the view center of every
+    /// sample of `tile`, one row per rayon task.
+    fn map_pixels<T, F>(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [T], f: F) -> Result<(), RenderError>
+        where T: Send, F: Fn(&Orbit, Complex<f64>) -> T + Sync + Send
+    {
+        check_tile(viewport, tile, out.len())?;
+        let orbit = self.orbit(viewport, params.limit)?;
+        let map = viewport.pixel_map();
+        let (half_w, half_h) = (viewport.width as f64 / 2.0, viewport.height as f64 / 2.0);
+        out.par_chunks_mut(tile.width)
+            .enumerate()
+            .try_for_each(|(j, row)| {
+                params.cancel.check()?;
+                for (i, value) in row.iter_mut().enumerate() {
+                    let (x, y) = tile.pixel(i, j);
+                    let (x, y) = (x as f64 - half_w, y as f64 - half_h);
+                    let dc = Complex {
+                        re: x * map.dx.re + y * map.dy.re,
+                        im: x * map.dx.im + y * map.dy.im,
+                    };
+                    *value = f(&orbit, dc);
+                }
+                Ok(())
+            })
+    }
+
+    fn escapes(orbit: &Orbit, dc: Complex<f64>, limit: u64, deep: bool) -> (u64, Complex<f64>) {
+        if deep {
+            orbit.escapes::<FloatExp>(dc, limit)
+        } else {
+            orbit.escapes::<f64>(dc, limit)
+        }
+    }
+
+    fn counts<C: Count>(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [C]) -> Result<(), RenderError> {
+        check_limit::<C>(params.limit)?;
+        let limit = params.limit as u64;
+        let deep = viewport.pixel_size() < FLOATEXP_BELOW;
+        self.map_pixels(viewport, tile, params, out, |orbit, dc| {
+            C::from_count(Self::escapes(orbit, dc, limit, deep).0)
+        })
+    }
+}
+
+impl MandelbrotRenderer for PerturbationMandelbrot {
+    fn new() -> Result<PerturbationMandelbrot, RenderError> {
+        Ok(PerturbationMandelbrot { orbit: Mutex::new(None) })
+    }
+    fn render_tile(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: Counts) -> Result<(), RenderError> {
+        match out {
+            Counts::U16(out) => self.counts(viewport, tile, params, out),
+            Counts::U32(out) => self.counts(viewport, tile, params, out),
+            Counts::F32(out) => self.counts(viewport, tile, params, out),
+        }
+    }
+    fn render_tile_smooth(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [f32]) -> Result<(), RenderError> {
+        let limit = params.limit as u64;
+        let deep = viewport.pixel_size() < FLOATEXP_BELOW;
+        self.map_pixels(viewport, tile, params, out, |orbit, dc| {
+            let (count, mut z) = Self::escapes(orbit, dc, limit, deep);
+            if count == limit {
+                return limit as f32;
+            }
+            // |z| > 2 dwarfs the digits f64 loses here
+            let c = viewport.center + dc;
+            for _ in 0..SMOOTH_EXTRA {
+                z = z*z + c;
+            }
+            smooth_count(count, z.norm_sqr())
+        })
+    }
+}
@@ -0,0 +1,56 @@
+use crate::error::RenderError;
+use crate::renderer::check_tile;
+use crate::viewport::{Viewport, Tile};
+
+/// Sample grids of every pass as (step, x offset, y offset), and the block size the
+/// image is complete to after the pass. Each pass only adds samples no earlier pass
+/// has computed: 1/16 of the pixels first, then 1/4, then all of them.
+const PASSES: [(&[(usize, usize, usize)], usize); 3] = [
+    (&[(4, 0, 0)], 4),
+    (&[(4, 2, 0), (4, 0, 2), (4, 2, 2)], 2),
+    (&[(2, 1, 0), (2, 0, 1), (2, 1, 1)], 1),
+];
+
+/// Renders `viewport` coarse to fine with `render_tile`, calling `on_pass` with the whole
+/// frame after every pass. Pixels that are not computed yet are copied from the sample
+/// at the top-left corner of their block. The final frame is returned as well.
+pub fn progressive<T, R, P>(viewport: &Viewport, render_tile: R, mut on_pass: P) -> Result<Vec<T>, RenderError>
+    where T: Copy + Default,
+          R: Fn(&Tile, &mut [T]) -> Result<(), RenderError>,
+          P: FnMut(&[T])
+{
+    let full = Tile::full(viewport);
+    check_tile(viewport, &full, full.len())?;
+    let (width, height) = viewport.dims();
+    let mut frame = vec![T::default(); width * height];
+    let mut samples = Vec::new();
+
+    for (grids, block) in PASSES.iter() {
+        for &(step, x, y) in grids.iter() {
+            let tile = Tile::grid(viewport, step, x, y);
+            if tile.is_empty() {
+                continue;
+            }
+            samples.resize(tile.len(), T::default());
+            render_tile(&tile, &mut samples)?;
+            for (idx, &value) in samples.iter().enumerate() {
+                let (x, y) = tile.pixel(idx % tile.width, idx / tile.width);
+                frame[y * width + x] = value;
+            }
+        }
+
+        if *block == 1 {
+            on_pass(&frame);
+        } else {
+            let preview = (0..width * height)
+                .map(|idx| {
+                    let x = idx % width;
+                    let y = idx / width;
+                    frame[(y - y % block) * width + x - x % block]
+                })
+                .collect::<Vec<T>>();
+            on_pass(&preview);
+        }
+    }
+    Ok(frame)
+}
@@ -0,0 +1,155 @@
+use crate::error::RenderError;
+use crate::renderer::MandelbrotRenderer;
+use crate::schedule::{Pool, Pooled};
+use crate::symmetry::Mirrored;
+use crate::{SingleMandelbrot, MultiMandelbrot, SIMDMandelbrot, OCLMandelbrot, MarianiSilver, PerturbationMandelbrot};
+use crate::{DDSingleMandelbrot, DDSIMDMandelbrot};
+
+/// Arithmetic a backend iterates in.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Precision {
+    F32,
+    F64,
+    /// Pairs of f64, about 106 bits, see [`DoubleDouble`](crate::dd::DoubleDouble).
+    DoubleDouble,
+    /// Pairs of f32, about 48 bits, for GPUs without f64.
+    DoubleFloat,
+    /// f64 deltas against a [`Fixed`](crate::fixed::Fixed) reference orbit. Holds up at
+    /// any zoom, but rounds differently from iterating in plain f64.
+    Perturbation,
+}
+
+/// Iteration formulas a backend can render.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Formula {
+    Mandelbrot,
+}
+
+#[derive(Clone, Debug)]
+pub struct Capabilities {
+    pub precision: Precision,
+    /// Largest iteration limit the backend accepts.
+    pub max_limit: usize,
+    pub formulas: &'static [Formula],
+    /// Whether every pixel is iterated. Backends that fill in pixels from their
+    /// neighbours, like the Mariani–Silver subdivision, can miss thin details.
+    pub exact: bool,
+}
+
+/// Builds a backend that renders on the given pool, see [`Registry::register_with`].
+pub type Factory = fn(&Pool) -> Result<Box<dyn MandelbrotRenderer>, RenderError>;
+
+/// A named backend that can be built on demand.
+pub struct Entry {
+    pub name: &'static str,
+    pub capabilities: Capabilities,
+    factory: Factory,
+    pool: Pool,
+}
+
+impl Entry {
+    pub fn create(&self) -> Result<Box<dyn MandelbrotRenderer>, RenderError> {
+        (self.factory)(&self.pool)
+    }
+
+    /// Builds the backend once to find out whether it works on this machine.
+    pub fn availability(&self) -> Result<(), RenderError> {
+        self.create().map(|_| ())
+    }
+}
+
+fn boxed<R: MandelbrotRenderer + 'static>(pool: &Pool) -> Result<Box<dyn MandelbrotRenderer>, RenderError> {
+    Mirrored::<R>::new().map(|renderer| Box::new(Pooled::wrap(renderer, pool.clone())) as Box<dyn MandelbrotRenderer>)
+}
+
+fn narrow_simd(pool: &Pool) -> Result<Box<dyn MandelbrotRenderer>, RenderError> {
+    Ok(Box::new(Pooled::wrap(Mirrored::wrap(SIMDMandelbrot::narrow()), pool.clone())))
+}
+
+/// Backends by name, in the order they were registered, and the thread pool they
+/// render on.
+///
+/// `Registry::default()` holds every backend in the crate on rayon's global pool and
+/// `Registry::with_threads` the same on a pool of its own; `Registry::empty()` starts
+/// with none.
+pub struct Registry {
+    entries: Vec<Entry>,
+    pool: Pool,
+}
+
+impl Registry {
+    pub fn empty() -> Registry {
+        Registry { entries: Vec::new(), pool: Pool::global() }
+    }
+
+    /// Every backend in the crate, rendering on `threads` threads.
+    pub fn with_threads(threads: usize) -> Result<Registry, RenderError> {
+        Ok(Registry::populated(Pool::with_threads(threads)?))
+    }
+
+    /// Registers `R`, wrapped in [`Mirrored`] to skip the rows that mirror others and in
+    /// [`Pooled`] to render on the pool of the registry.
+    pub fn register<R: MandelbrotRenderer + 'static>(&mut self, name: &'static str, capabilities: Capabilities) {
+        self.register_with(name, capabilities, boxed::<R>);
+    }
+
+    /// `register` for a backend built by something other than `MandelbrotRenderer::new`;
+    /// `factory` wraps it in [`Mirrored`] and [`Pooled`] itself.
+    pub fn register_with(&mut self, name: &'static str, capabilities: Capabilities, factory: Factory) {
+        let pool = self.pool.clone();
+        self.entries.push(Entry { name, capabilities, factory, pool });
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.entries.iter().map(|entry| entry.name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    pub fn create(&self, name: &str) -> Result<Box<dyn MandelbrotRenderer>, RenderError> {
+        self.get(name)
+            .ok_or_else(|| RenderError::UnknownRenderer(name.to_string()))?
+            .create()
+    }
+
+    /// Every backend in the crate, rendering on `pool`.
+    fn populated(pool: Pool) -> Registry {
+        let cpu = Capabilities {
+            precision: Precision::F64,
+            max_limit: usize::MAX,
+            formulas: &[Formula::Mandelbrot],
+            exact: true,
+        };
+        let mut registry = Registry { entries: Vec::new(), pool };
+        registry.register::<SingleMandelbrot>("single", cpu.clone());
+        registry.register::<MultiMandelbrot>("multi", cpu.clone());
+        registry.register::<SIMDMandelbrot>("simd", cpu.clone());
+        // f32 lanes for shallow views, f64 past them
+        registry.register_with("simd-f32", Capabilities { precision: Precision::F32, ..cpu.clone() }, narrow_simd);
+        // the coarsest it renders in, finer views get f64 or double-float
+        registry.register::<OCLMandelbrot>("opencl", Capabilities {
+            precision: Precision::F32,
+            max_limit: i32::MAX as usize,
+            formulas: &[Formula::Mandelbrot],
+            exact: true,
+        });
+        registry.register::<MarianiSilver>("mariani", Capabilities { exact: false, ..cpu.clone() });
+        let dd = Capabilities { precision: Precision::DoubleDouble, ..cpu.clone() };
+        registry.register::<DDSingleMandelbrot>("dd-single", dd.clone());
+        registry.register::<DDSIMDMandelbrot>("dd-simd", dd);
+        registry.register::<PerturbationMandelbrot>("perturbation", Capabilities { precision: Precision::Perturbation, ..cpu });
+        registry
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::populated(Pool::global())
+    }
+}
@@ -1,6 +1,189 @@
-use std::error::Error;
+use crate::error::RenderError;
+use crate::progressive::progressive;
+use crate::registry::Precision;
+use crate::viewport::{Viewport, Tile};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-pub trait MandelbrotRenderer {
-    fn new(dims: (usize, usize)) -> Self where Self: Sized;
-    fn render(&self, xr: std::ops::Range<f64>, yr: std::ops::Range<f64>, limit: usize) -> Result<Vec<u64>, Box<dyn Error>>;
-}
\ No newline at end of file
+/// Shared flag a caller sets to stop a render early. Backends check it between rows
+/// (or enqueued bands of rows) and give up with `RenderError::Cancelled`.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn check(&self) -> Result<(), RenderError> {
+        if self.is_cancelled() {
+            return Err(RenderError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+/// Per-render settings shared by every tile of a frame.
+#[derive(Clone, Debug, Default)]
+pub struct RenderParams {
+    /// Iteration limit, the count of pixels that never escape.
+    pub limit: usize,
+    pub cancel: CancelToken,
+}
+
+impl RenderParams {
+    pub fn new(limit: usize) -> RenderParams {
+        RenderParams { limit, cancel: CancelToken::new() }
+    }
+}
+
+/// Escape count type of an output buffer, see [`Counts`].
+pub trait Count: Copy + Default + PartialEq + Send + Sync + 'static {
+    /// Largest count the type holds exactly, and so the largest usable limit.
+    const MAX: u64;
+    fn from_count(count: u64) -> Self;
+    /// `out` as the matching [`Counts`] variant.
+    fn counts(out: &mut [Self]) -> Counts;
+}
+
+impl Count for u16 {
+    const MAX: u64 = u16::MAX as u64;
+    #[inline]
+    fn from_count(count: u64) -> u16 {
+        count as u16
+    }
+    fn counts(out: &mut [u16]) -> Counts {
+        Counts::U16(out)
+    }
+}
+
+impl Count for u32 {
+    const MAX: u64 = u32::MAX as u64;
+    #[inline]
+    fn from_count(count: u64) -> u32 {
+        count as u32
+    }
+    fn counts(out: &mut [u32]) -> Counts {
+        Counts::U32(out)
+    }
+}
+
+impl Count for f32 {
+    const MAX: u64 = 1 << f32::MANTISSA_DIGITS;
+    #[inline]
+    fn from_count(count: u64) -> f32 {
+        count as f32
+    }
+    fn counts(out: &mut [f32]) -> Counts {
+        Counts::F32(out)
+    }
+}
+
+/// Caller-owned output buffer of [`MandelbrotRenderer::render_tile`]; the variant picks
+/// the count type. Smaller types cut the memory traffic but cap the iteration limit.
+pub enum Counts<'a> {
+    U16(&'a mut [u16]),
+    U32(&'a mut [u32]),
+    /// Integer counts as floats, ready for `Splines::colorize` in the viewer.
+    F32(&'a mut [f32]),
+}
+
+/// Renderers are shared with the worker thread, hence `Send + Sync`.
+pub trait MandelbrotRenderer: Send + Sync {
+    fn new() -> Result<Self, RenderError> where Self: Sized;
+    /// Writes the escape counts (see `single::escapes`) of the samples of `tile` into
+    /// `out`, row by row. `out` must hold exactly `tile.len()` values and its count type
+    /// must fit `params.limit`.
+    /// Returns `RenderError::Cancelled` soon after `params.cancel` is set.
+    fn render_tile(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: Counts) -> Result<(), RenderError>;
+    /// Like `render_tile`, but escaped pixels get a fractional count (see [`smooth_count`])
+    /// so palettes can be sampled without bands. Pixels inside the set are `limit`.
+    fn render_tile_smooth(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [f32]) -> Result<(), RenderError>;
+    /// The arithmetic `viewport` is rendered in up to `limit`, for backends that pick one
+    /// per view. `None` for the rest, which always iterate in their registered precision.
+    fn precision(&self, _viewport: &Viewport, _limit: usize) -> Option<Precision> {
+        None
+    }
+    /// Whether every sample of `viewport` is iterated on its own from its point in
+    /// `viewport.pixel_map()`, in arithmetic that gives conjugate points the same value.
+    /// [`Mirrored`](crate::symmetry::Mirrored) then renders only one of two mirrored rows.
+    fn conjugate_symmetric(&self, _viewport: &Viewport) -> bool {
+        false
+    }
+
+    fn render(&self, viewport: &Viewport, limit: usize) -> Result<Vec<u32>, RenderError> {
+        let tile = Tile::full(viewport);
+        let mut out = vec![0; tile.len()];
+        self.render_tile(viewport, &tile, &RenderParams::new(limit), Counts::U32(&mut out))?;
+        Ok(out)
+    }
+    fn render_smooth(&self, viewport: &Viewport, limit: usize) -> Result<Vec<f32>, RenderError> {
+        let tile = Tile::full(viewport);
+        let mut out = vec![0.0; tile.len()];
+        self.render_tile_smooth(viewport, &tile, &RenderParams::new(limit), &mut out)?;
+        Ok(out)
+    }
+    /// Renders coarse to fine and hands every intermediate frame to `on_pass`;
+    /// see [`progressive`]. Returns the same counts as `render`.
+    fn render_progressive(&self, viewport: &Viewport, params: &RenderParams, on_pass: &mut dyn FnMut(&[u32])) -> Result<Vec<u32>, RenderError> {
+        progressive(viewport, |tile, out| self.render_tile(viewport, tile, params, Counts::U32(out)), on_pass)
+    }
+    fn render_progressive_smooth(&self, viewport: &Viewport, params: &RenderParams, on_pass: &mut dyn FnMut(&[f32])) -> Result<Vec<f32>, RenderError> {
+        progressive(viewport, |tile, out| self.render_tile_smooth(viewport, tile, params, out), on_pass)
+    }
+}
+
+/// Iterations run past the escape before |z| is sampled for the smooth count.
+/// A larger |z| makes the log-log estimate closer to continuous.
+pub const SMOOTH_EXTRA: u64 = 2;
+
+/// Continuous escape count, `count - log2(log2 |z|)` shifted back by the extra iterations.
+///
+/// `count` is the integer count from `single::escapes` and `norm_sqr` is |z|^2 taken
+/// `SMOOTH_EXTRA` iterations after the escape.
+#[inline]
+pub fn smooth_count(count: u64, norm_sqr: f64) -> f32 {
+    let log_z = 0.5 * norm_sqr.log2();
+    ((count + SMOOTH_EXTRA) as f64 - log_z.log2()).max(0.0) as f32
+}
+
+/// Smallest pixel, relative to the view center (at least 1), that f32 still renders
+/// without blocks: about 8 ulps.
+const F32_PIXEL: f64 = 1e-6;
+
+/// Whether f32 tells the neighbouring pixels of `viewport` apart.
+pub(crate) fn f32_resolves(viewport: &Viewport) -> bool {
+    viewport.pixel_size() >= F32_PIXEL * viewport.center.norm().max(1.0)
+}
+
+pub(crate) fn check_limit<C: Count>(limit: usize) -> Result<(), RenderError> {
+    if limit as u64 > C::MAX {
+        return Err(RenderError::LimitTooLarge { limit, max: C::MAX });
+    }
+    Ok(())
+}
+
+/// Checks that `tile` lies inside `viewport` and that the output buffer has room for
+/// exactly its `out_len` samples.
+pub(crate) fn check_tile(viewport: &Viewport, tile: &Tile, out_len: usize) -> Result<(), RenderError> {
+    if viewport.width == 0 || viewport.height == 0 || tile.is_empty() || tile.step == 0 {
+        return Err(RenderError::InvalidDims { width: tile.width, height: tile.height });
+    }
+    if out_len != tile.len() {
+        return Err(RenderError::BufferSize { expected: tile.len(), got: out_len });
+    }
+    let (last_x, last_y) = tile.pixel(tile.width - 1, tile.height - 1);
+    if last_x >= viewport.width || last_y >= viewport.height {
+        return Err(RenderError::TileOutOfBounds(*tile));
+    }
+    Ok(())
+}
@@ -0,0 +1,190 @@
+use crate::error::RenderError;
+use crate::registry::Precision;
+use crate::renderer::{MandelbrotRenderer, RenderParams, Counts, CancelToken};
+use crate::single::escapes_fast;
+use crate::viewport::{Viewport, Tile};
+use num::Complex;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::{Arc, Mutex};
+
+/// Samples per side of a scheduled block.
+const BLOCK: usize = 32;
+
+/// The cost pre-pass probes every `PROBE_STEP`-th sample of a block in both directions.
+const PROBE_STEP: usize = 8;
+
+/// Probes iterate to at most `1 / PROBE_SHARE` of the limit. With one sample in
+/// `PROBE_STEP^2` that is well under 1% of the iterations of the render, which only
+/// needs the order of the blocks, not their exact cost.
+const PROBE_SHARE: u64 = 16;
+
+/// Thread pool backends render on: rayon's global one, or one of their own.
+#[derive(Clone)]
+pub struct Pool(Option<Arc<ThreadPool>>);
+
+impl Pool {
+    /// Rayon's global pool, one thread per core unless the application set it up
+    /// otherwise.
+    pub fn global() -> Pool {
+        Pool(None)
+    }
+
+    /// A pool of its own with `threads` threads, for the backends of
+    /// [`Registry::with_threads`](crate::Registry::with_threads).
+    pub fn with_threads(threads: usize) -> Result<Pool, RenderError> {
+        ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map(|pool| Pool(Some(Arc::new(pool))))
+            .map_err(|e| RenderError::ThreadPool(e.to_string()))
+    }
+
+    /// Runs `f` inside the pool, where rayon's parallel iterators use its threads.
+    pub fn install<R: Send, F: FnOnce() -> R + Send>(&self, f: F) -> R {
+        match &self.0 {
+            Some(pool) => pool.install(f),
+            None => f(),
+        }
+    }
+}
+
+/// Renders `R` inside a [`Pool`], where all of its parallel iterators run.
+pub struct Pooled<R> {
+    inner: R,
+    pool: Pool,
+}
+
+impl<R: MandelbrotRenderer> Pooled<R> {
+    pub fn wrap(inner: R, pool: Pool) -> Pooled<R> {
+        Pooled { inner, pool }
+    }
+}
+
+impl<R: MandelbrotRenderer> MandelbrotRenderer for Pooled<R> {
+    fn new() -> Result<Pooled<R>, RenderError> {
+        R::new().map(|inner| Pooled::wrap(inner, Pool::global()))
+    }
+    fn render_tile(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: Counts) -> Result<(), RenderError> {
+        self.pool.install(|| self.inner.render_tile(viewport, tile, params, out))
+    }
+    fn render_tile_smooth(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [f32]) -> Result<(), RenderError> {
+        self.pool.install(|| self.inner.render_tile_smooth(viewport, tile, params, out))
+    }
+    fn precision(&self, viewport: &Viewport, limit: usize) -> Option<Precision> {
+        self.inner.precision(viewport, limit)
+    }
+    fn conjugate_symmetric(&self, viewport: &Viewport) -> bool {
+        self.inner.conjugate_symmetric(viewport)
+    }
+}
+
+/// Rough cost of iterating `c`: its escape count, up to `limit / PROBE_SHARE`. The main
+/// bulbs are rejected before the first iteration and cost nothing.
+pub fn probe_cost(c: Complex<f64>, limit: u64) -> u64 {
+    escapes_fast(c, (limit / PROBE_SHARE).max(1))
+}
+
+/// The blocks of `tile`, row by row.
+fn blocks(tile: &Tile) -> Vec<Tile> {
+    let mut blocks = Vec::new();
+    for row in (0..tile.height).step_by(BLOCK) {
+        for col in (0..tile.width).step_by(BLOCK) {
+            let (x, y) = tile.pixel(col, row);
+            let (width, height) = (BLOCK.min(tile.width - col), BLOCK.min(tile.height - row));
+            blocks.push(Tile { x, y, width, height, step: tile.step });
+        }
+    }
+    blocks
+}
+
+/// Sum of `cost` over a sparse grid of the samples of `tile`.
+fn estimate<E: Fn(usize, usize) -> u64>(tile: &Tile, cost: &E) -> u64 {
+    let (first_i, first_j) = ((PROBE_STEP / 2).min(tile.width - 1), (PROBE_STEP / 2).min(tile.height - 1));
+    let mut sum = 0;
+    for j in (first_j..tile.height).step_by(PROBE_STEP) {
+        for i in (first_i..tile.width).step_by(PROBE_STEP) {
+            let (x, y) = tile.pixel(i, j);
+            sum += cost(x, y);
+        }
+    }
+    sum
+}
+
+/// Renders `tile` into `out` in blocks of `BLOCK` x `BLOCK` samples, the most expensive
+/// first, on the threads of the current rayon pool.
+///
+/// `cost(x, y)` guesses the work of viewport pixel (x, y), see [`probe_cost`]; a sparse
+/// pre-pass over every block adds them up. Cost varies wildly across a view, and blocks
+/// taken in that order leave no thread with a slow block to finish alone at the end.
+/// `render(block, rows)` renders the samples of a block straight into its parts of the
+/// rows of `out`.
+pub fn by_cost<T, E, R>(tile: &Tile, out: &mut [T], cancel: &CancelToken, cost: E, render: R) -> Result<(), RenderError>
+    where T: Send,
+          E: Fn(usize, usize) -> u64 + Sync,
+          R: Fn(&Tile, &mut [&mut [T]]) -> Result<(), RenderError> + Sync
+{
+    let blocks = blocks(tile);
+    let costs: Vec<u64> = blocks.par_iter().map(|block| estimate(block, &cost)).collect();
+
+    let columns = tile.width.div_ceil(BLOCK);
+    let mut rows: Vec<Vec<&mut [T]>> = blocks.iter().map(|block| Vec::with_capacity(block.height)).collect();
+    for (j, mut row) in out.chunks_mut(tile.width).enumerate() {
+        for col in 0..columns {
+            let width = BLOCK.min(row.len());
+            let (part, rest) = std::mem::take(&mut row).split_at_mut(width);
+            rows[j / BLOCK * columns + col].push(part);
+            row = rest;
+        }
+    }
+    let mut queue: Vec<_> = blocks.into_iter().zip(rows).zip(costs).collect();
+    queue.sort_unstable_by(|(_, a), (_, b)| b.cmp(a));
+    let threads = rayon::current_num_threads().min(queue.len());
+    let queue = Mutex::new(queue.into_iter());
+
+    // every thread takes the next block in order until none are left
+    (0..threads).into_par_iter().try_for_each(|_| -> Result<(), RenderError> {
+        loop {
+            let next = queue.lock()
+                .map_err(|_| RenderError::Poisoned("block queue"))?
+                .next();
+            let ((block, mut rows), _) = match next {
+                Some(next) => next,
+                None => return Ok(()),
+            };
+            cancel.check()?;
+            render(&block, &mut rows)?;
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn blocks_cover_tile() {
+        // odd sizes and a sample step, more blocks than threads
+        let tile = Tile { x: 3, y: 1, width: 77, height: 45, step: 2 };
+        let mut out = vec![(0, 0); tile.len()];
+        let pixel = |block: &Tile, rows: &mut [&mut [(usize, usize)]]| -> Result<(), RenderError> {
+            assert_eq!(rows.len(), block.height);
+            for (j, row) in rows.iter_mut().enumerate() {
+                assert_eq!(row.len(), block.width);
+                for (i, value) in row.iter_mut().enumerate() {
+                    *value = block.pixel(i, j);
+                }
+            }
+            Ok(())
+        };
+        by_cost(&tile, &mut out, &CancelToken::new(), |x, y| (x * y) as u64, pixel).unwrap();
+        for (idx, &value) in out.iter().enumerate() {
+            assert_eq!(value, tile.pixel(idx % tile.width, idx / tile.width));
+        }
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let result = by_cost(&tile, &mut out, &cancel, |_, _| 0, pixel);
+        assert!(matches!(result, Err(RenderError::Cancelled)));
+    }
+}
@@ -0,0 +1,57 @@
+use crate::error::RenderError;
+use crate::renderer::check_tile;
+use crate::viewport::{Viewport, Tile};
+
+/// Builds the frame of `viewport` from `previous`, the finished frame of the viewport
+/// it was panned from by `offset` whole pixels (see [`Viewport::pixel_offset`]).
+///
+/// Pixels that stay in view are copied from `previous`, only the strips the pan exposed
+/// are rendered with `render_tile`. A pan by a whole frame or more renders everything.
+pub fn shift<T, R>(viewport: &Viewport, previous: &[T], offset: (isize, isize), render_tile: R) -> Result<Vec<T>, RenderError>
+    where T: Copy + Default,
+          R: Fn(&Tile, &mut [T]) -> Result<(), RenderError>
+{
+    let full = Tile::full(viewport);
+    check_tile(viewport, &full, full.len())?;
+    let (width, height) = viewport.dims();
+    let (dx, dy) = offset;
+    let (dx_abs, dy_abs) = (dx.abs() as usize, dy.abs() as usize);
+    let mut frame = vec![T::default(); width * height];
+    if previous.len() != width * height || dx_abs >= width || dy_abs >= height {
+        render_tile(&full, &mut frame)?;
+        return Ok(frame);
+    }
+
+    // pixel (x, y) of the new frame is pixel (x + dx, y + dy) of the previous one
+    let (from_x, to_x) = ((-dx).max(0) as usize, (width as isize - dx).min(width as isize) as usize);
+    for y in 0..height {
+        let src_y = y as isize + dy;
+        if src_y < 0 || src_y >= height as isize {
+            continue;
+        }
+        let src = src_y as usize * width + (from_x as isize + dx) as usize;
+        frame[y * width + from_x..y * width + to_x].copy_from_slice(&previous[src..src + to_x - from_x]);
+    }
+
+    let mut strips = Vec::with_capacity(2);
+    if dy_abs > 0 {
+        let y = if dy > 0 { height - dy_abs } else { 0 };
+        strips.push(Tile { x: 0, y, width, height: dy_abs, step: 1 });
+    }
+    if dx_abs > 0 {
+        let x = if dx > 0 { width - dx_abs } else { 0 };
+        // the rows the horizontal strip does not cover
+        let y = if dy > 0 { 0 } else { dy_abs };
+        strips.push(Tile { x, y, width: dx_abs, height: height - dy_abs, step: 1 });
+    }
+    let mut samples = Vec::new();
+    for tile in strips.iter() {
+        samples.resize(tile.len(), T::default());
+        render_tile(tile, &mut samples)?;
+        for (j, row) in samples.chunks(tile.width).enumerate() {
+            let start = (tile.y + j) * width + tile.x;
+            frame[start..start + tile.width].copy_from_slice(row);
+        }
+    }
+    Ok(frame)
+}
@@ -1,117 +1,227 @@
 
-use crate::renderer::{MandelbrotRenderer};
-use std::error::Error;
+use crate::renderer::{MandelbrotRenderer, RenderParams, Count, Counts, check_tile, check_limit, f32_resolves, smooth_count, SMOOTH_EXTRA};
+use crate::error::RenderError;
+use crate::kernel::{self, Float};
+use crate::registry::Precision;
+use crate::schedule::{self, probe_cost};
+use crate::viewport::{Viewport, Tile};
+use num::Complex;
 use packed_simd::*;
-use rayon::prelude::*;
 
-pub struct SIMDMandelbrot{
-    dims: (usize, usize)
+/// One pixel per vector lane, blocks of the view in parallel, the most expensive first
+/// (see [`schedule::by_cost`]). Each block is a queue the lanes pull their pixels from:
+/// a lane that is done takes the next pixel right away instead of waiting for the
+/// slowest lane, see `kernel::escapes_refill`. Any tile width works.
+///
+/// The vector width follows the instruction set the CPU has at run time (see [`Isa`]),
+/// so one binary is fast everywhere. The narrow variant also renders shallow views in
+/// f32 lanes, twice as many per vector.
+pub struct SIMDMandelbrot {
+    isa: Isa,
+    narrow: bool,
 }
 
-#[derive(Copy, Clone)]
-struct Complexx8 {
-    real: f64x8,
-    imag: f64x8
+/// Instruction sets the kernels are compiled for, best first.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Isa {
+    /// f64x8 and f32x16.
+    Avx512,
+    /// f64x4 and f32x8.
+    Avx2,
+    /// f64x2.
+    Sse2,
+    /// f64x4 in whatever the target was built for.
+    Portable,
 }
-impl Complexx8 {
-    #[inline]
-    fn escapes(self, threshold: f64, limit: usize) -> u64x8 {
-        let mut count = u64x8::splat(0);
-        let mut z = self;
-        for _ in 0..limit {
-            let x = z.real;
-            let y = z.imag;
-
-            let xx = x * x;
-            let yy = y * y;
-            let sum = xx + yy;
-
-            let escapes = sum.le(f64x8::splat(threshold));
-            if escapes.none() {
-                break
+
+impl Isa {
+    pub const ALL: [Isa; 4] = [Isa::Avx512, Isa::Avx2, Isa::Sse2, Isa::Portable];
+
+    /// The best instruction set of this CPU.
+    pub fn detect() -> Isa {
+        Isa::ALL.iter().copied().find(|isa| isa.is_supported()).unwrap_or(Isa::Portable)
+    }
+
+    pub fn is_supported(self) -> bool {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            match self {
+                Isa::Avx512 => is_x86_feature_detected!("avx512f"),
+                Isa::Avx2 => is_x86_feature_detected!("avx2"),
+                Isa::Sse2 => is_x86_feature_detected!("sse2"),
+                Isa::Portable => true,
             }
-            count += escapes.select(u64x8::splat(1), u64x8::splat(0));
-            z = z.next_point(self);
         }
-        count
+        #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+        {
+            self == Isa::Portable
+        }
     }
-    fn next_point(self, start: Complexx8) -> Complexx8 {
-        let Complexx8 { real: c_x, imag: c_y } = start;
-        let Complexx8 { real: x, imag: y } = self;
 
-        let xx = x * x;
-        let yy = y * y;
-        let xy = x * y;
+    /// `kernel::escapes_refill` compiled for this instruction set.
+    #[inline]
+    fn escapes_refill<F: Float>(self, len: usize, point: &Point, limit: u64, extra: u64, store: &mut Store) {
+        // safe: `SIMDMandelbrot` only holds instruction sets the CPU supports
+        match self {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Isa::Avx512 => unsafe { escapes_refill_avx512::<F>(len, point, limit, extra, store) },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Isa::Avx2 => unsafe { escapes_refill_avx2::<F>(len, point, limit, extra, store) },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Isa::Sse2 => unsafe { escapes_refill_sse2::<F>(len, point, limit, extra, store) },
+            _ => kernel::escapes_refill::<F, _, _>(len, point, limit, extra, true, store),
+        }
+    }
+}
 
-        let new_x = c_x + (xx - yy);
-        let new_y = c_y + (xy + xy);
+/// Point of the i-th sample of a block.
+type Point<'a> = dyn Fn(usize) -> Complex<f64> + 'a;
+/// Takes the count and |z|^2 of the i-th sample of a block.
+type Store<'a> = dyn FnMut(usize, u64, f64) + 'a;
+/// Writes the value of the i-th sample of a block.
+type Put<'a, T> = dyn FnMut(usize, T) + 'a;
+
+/// `kernel::escapes_refill` with the iteration inlined into code for `$feature`.
+macro_rules! isa_kernel {
+    ($name:ident, $feature:tt) => {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        #[target_feature(enable = $feature)]
+        unsafe fn $name<F: Float>(len: usize, point: &Point, limit: u64, extra: u64, store: &mut Store) {
+            kernel::escapes_refill::<F, _, _>(len, point, limit, extra, true, store)
+        }
+    };
+}
 
-        Complexx8 { real: new_x, imag: new_y}
-    }
+isa_kernel!(escapes_refill_avx512, "avx512f");
+isa_kernel!(escapes_refill_avx2, "avx2");
+isa_kernel!(escapes_refill_sse2, "sse2");
+
+/// Runs `$body` with `$alias` naming the vector type of `$lanes`.
+macro_rules! with_lanes {
+    ($lanes:expr, $alias:ident => $body:expr) => {
+        match $lanes {
+            Lanes::F64x2 => { type $alias = f64x2; $body }
+            Lanes::F64x4 => { type $alias = f64x4; $body }
+            Lanes::F64x8 => { type $alias = f64x8; $body }
+            Lanes::F32x8 => { type $alias = f32x8; $body }
+            Lanes::F32x16 => { type $alias = f32x16; $body }
+        }
+    };
 }
 
+/// Vector type a view is rendered in.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Lanes {
+    F64x2,
+    F64x4,
+    F64x8,
+    F32x8,
+    F32x16,
+}
 
-impl MandelbrotRenderer for SIMDMandelbrot {
-    fn new(dims: (usize, usize)) -> SIMDMandelbrot {
-       SIMDMandelbrot{dims}
+impl SIMDMandelbrot {
+    /// `isa` in f64 lanes, or in f32 lanes for shallow views if `narrow`. `None` if the
+    /// CPU does not have `isa`.
+    pub fn with_isa(isa: Isa, narrow: bool) -> Option<SIMDMandelbrot> {
+        if isa.is_supported() {
+            Some(SIMDMandelbrot { isa, narrow })
+        } else {
+            None
+        }
     }
-    fn render(&self, xr: std::ops::Range<f64>, yr: std::ops::Range<f64>, limit: usize) ->Result<Vec<u64>, Box<dyn Error>> {
-        let (width, height) = self.dims;
-
-        let block_size = f64x8::lanes();
-
-        assert_eq!(
-            width % block_size,
-            0,
-            "image width = {} is not divisible by the number of vector lanes = {}",
-            width,
-            block_size,
-        );
-
-        let width_in_blocks = width / block_size;
-
-        let xs = unsafe {
-            let dx = (xr.end - xr.start) / (width as f64);
-            let mut buf: Vec<f64x8> = vec![f64x8::splat(0.); width_in_blocks];
-
-            std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut f64, width)
-                .iter_mut()
-                .enumerate()
-                .for_each(|(j, x)| {
-                    *x = xr.start + dx * (j as f64);
-                });
 
-            buf
-        };
+    /// The narrow variant on the best instruction set of this CPU.
+    pub fn narrow() -> SIMDMandelbrot {
+        SIMDMandelbrot { isa: Isa::detect(), narrow: true }
+    }
 
-        let dy = (yr.end - yr.start) / (height as f64);
+    pub fn isa(&self) -> Isa {
+        self.isa
+    }
 
-        let len = width_in_blocks * height;
-        let mut out = Vec::with_capacity(len);
-        unsafe {
-            out.set_len(len);
+    /// The arithmetic `viewport` is rendered in up to `limit`: f32 for views it tells
+    /// apart when narrow and the CPU has 8 or more f32 lanes, f64 otherwise.
+    pub fn precision_for(&self, viewport: &Viewport, limit: usize) -> Precision {
+        let wide = self.isa == Isa::Avx512 || self.isa == Isa::Avx2;
+        // counts are kept in the lanes, f32 holds them up to 2^24
+        if self.narrow && wide && f32_resolves(viewport) && limit as u64 <= <f32 as Count>::MAX {
+            Precision::F32
+        } else {
+            Precision::F64
         }
+    }
 
-        out.par_chunks_mut(width_in_blocks).enumerate().for_each(|(i, row)| {
-            let y = f64x8::splat(yr.start + dy * (i as f64));
-            row.iter_mut().enumerate().for_each(|(j, count)| {
-                let x = xs[j];
-                let z = Complexx8 { real: x, imag: y };
-                *count = z.escapes(4.0, limit);
-            });
-        });
-
-        let result = unsafe {
-            let mut out: Vec<u64> = std::mem::transmute(out);
-            // This is safe, we're transmuting from a more-aligned type to a
-            // less-aligned one.
-            out.set_len(width * height);
-            out
-        };
-        Ok(result)
+    fn lanes(&self, viewport: &Viewport, limit: usize) -> Lanes {
+        let narrow = self.precision_for(viewport, limit) == Precision::F32;
+        match self.isa {
+            Isa::Avx512 if narrow => Lanes::F32x16,
+            Isa::Avx512 => Lanes::F64x8,
+            Isa::Avx2 if narrow => Lanes::F32x8,
+            Isa::Avx2 | Isa::Portable => Lanes::F64x4,
+            Isa::Sse2 => Lanes::F64x2,
+        }
+    }
+
+    /// Calls `f` with the points of every block of `tile`, its number of samples and
+    /// where its values go in `out`.
+    fn map_blocks<T, G>(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [T], f: G) -> Result<(), RenderError>
+        where T: Send, G: Fn(&Point, usize, &mut Put<T>) + Sync + Send
+    {
+        check_tile(viewport, tile, out.len())?;
+        let map = viewport.pixel_map();
+        let limit = params.limit as u64;
+        let cost = |x: usize, y: usize| probe_cost(map.at(x as f64, y as f64), limit);
+        schedule::by_cost(tile, out, &params.cancel, cost, |block, rows| {
+            let width = block.width;
+            let point = |i: usize| {
+                let (x, y) = block.pixel(i % width, i / width);
+                map.at(x as f64, y as f64)
+            };
+            f(&point, block.len(), &mut |i, value| rows[i / width][i % width] = value);
+            Ok(())
+        })
+    }
+
+    fn counts<C: Count>(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [C]) -> Result<(), RenderError> {
+        check_limit::<C>(params.limit)?;
+        let (isa, limit) = (self.isa, params.limit as u64);
+        with_lanes!(self.lanes(viewport, params.limit), V => {
+            self.map_blocks(viewport, tile, params, out, |point, len, put| {
+                isa.escapes_refill::<V>(len, point, limit, 0, &mut |i, count, _| put(i, C::from_count(count)));
+            })
+        })
     }
 }
 
+impl MandelbrotRenderer for SIMDMandelbrot {
+    fn new() -> Result<SIMDMandelbrot, RenderError> {
+        Ok(SIMDMandelbrot { isa: Isa::detect(), narrow: false })
+    }
+    fn render_tile(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: Counts) -> Result<(), RenderError> {
+        match out {
+            Counts::U16(out) => self.counts(viewport, tile, params, out),
+            Counts::U32(out) => self.counts(viewport, tile, params, out),
+            Counts::F32(out) => self.counts(viewport, tile, params, out),
+        }
+    }
+    fn render_tile_smooth(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [f32]) -> Result<(), RenderError> {
+        let (isa, limit) = (self.isa, params.limit as u64);
+        with_lanes!(self.lanes(viewport, params.limit), V => {
+            self.map_blocks(viewport, tile, params, out, |point, len, put| {
+                isa.escapes_refill::<V>(len, point, limit, SMOOTH_EXTRA, &mut |i, count, norm| {
+                    put(i, if count == limit { limit as f32 } else { smooth_count(count, norm) });
+                });
+            })
+        })
+    }
+    fn precision(&self, viewport: &Viewport, limit: usize) -> Option<Precision> {
+        if self.narrow { Some(self.precision_for(viewport, limit)) } else { None }
+    }
+    fn conjugate_symmetric(&self, _viewport: &Viewport) -> bool {
+        // f32 lanes round conjugate points to conjugates
+        true
+    }
+}
 
 
 
@@ -127,4 +237,4 @@ impl MandelbrotRenderer for SIMDMandelbrot {
 //        let yr = std::ops::Range{start: -1., end: -2.};
 //        assert_eq!(renderer.generate(dims, xr, yr, 100).expect("error"), vec![1,1]);
 //    }
-//}
\ No newline at end of file
+//}
@@ -1,44 +1,88 @@
 
-use crate::renderer::{MandelbrotRenderer};
+use crate::renderer::{MandelbrotRenderer, RenderParams, Count, Counts, check_tile, check_limit};
+use crate::error::RenderError;
+use crate::kernel;
+use crate::viewport::{Viewport, Tile};
 use num::Complex;
-use std::error::Error;
 
-pub struct SingleMandelbrot{
-    dims: (usize, usize)
+pub struct SingleMandelbrot;
+
+impl SingleMandelbrot {
+    fn map_pixels<T, F: Fn(Complex<f64>) -> T>(viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [T], f: F) -> Result<(), RenderError> {
+        check_tile(viewport, tile, out.len())?;
+        let map = viewport.pixel_map();
+        for (j, row) in out.chunks_mut(tile.width).enumerate() {
+            params.cancel.check()?;
+            for (i, value) in row.iter_mut().enumerate() {
+                let (x, y) = tile.pixel(i, j);
+                *value = f(map.at(x as f64, y as f64));
+            }
+        }
+        Ok(())
+    }
+
+    fn counts<C: Count>(viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [C]) -> Result<(), RenderError> {
+        check_limit::<C>(params.limit)?;
+        let limit = params.limit as u64;
+        Self::map_pixels(viewport, tile, params, out, |point| C::from_count(escapes_fast(point, limit)))
+    }
 }
 
 impl MandelbrotRenderer for SingleMandelbrot {
-    fn new(dims: (usize, usize)) -> SingleMandelbrot {
-        SingleMandelbrot { dims }
+    fn new() -> Result<SingleMandelbrot, RenderError> {
+        Ok(SingleMandelbrot)
     }
-    fn render(&self, xr: std::ops::Range<f64>, yr: std::ops::Range<f64>, limit: usize) -> Result<Vec<u64>, Box<dyn Error>> {
-        let (width, height) = self.dims;
-        let colors = (0..(width * height) as usize)
-            .map(|idx| {
-                let x = idx % (width as usize) ;
-                let y = idx / (width as usize);
-                let dx = (xr.end - xr.start) / (width as f64);
-                let dy = (yr.end - yr.start) / (height as f64);
-                let point = Complex { re: xr.start + x as f64 * dx, im: yr.start + y as f64 * dy };
-                escapes(
-                    point,
-                    limit as u64,
-                )
-            })
-            .collect::<Vec<u64>>();
-        Ok(colors)
+    fn render_tile(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: Counts) -> Result<(), RenderError> {
+        match out {
+            Counts::U16(out) => Self::counts(viewport, tile, params, out),
+            Counts::U32(out) => Self::counts(viewport, tile, params, out),
+            Counts::F32(out) => Self::counts(viewport, tile, params, out),
+        }
+    }
+    fn render_tile_smooth(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [f32]) -> Result<(), RenderError> {
+        let limit = params.limit as u64;
+        Self::map_pixels(viewport, tile, params, out, |point| escapes_smooth_fast(point, limit))
+    }
+    fn conjugate_symmetric(&self, _viewport: &Viewport) -> bool {
+        true
     }
 }
 
+/// Reference escape count every backend has to reproduce.
+///
+/// Iterates `z = z*z + c` from `z = 0` and returns the 0-based index of the iteration
+/// after which `|z| > 2`, or `limit` if the orbit stays bounded for `limit` iterations.
+/// This is `kernel::iterate` on a single f64 without the interior shortcuts.
 #[inline]
-fn escapes(c: Complex<f64>, limit: u64) -> u64 {
-    let mut z = Complex { re: 0.0, im: 0.0 };
-    for i in 0..limit {
-        z = z*z + c;
-        if z.norm_sqr() > 4.0 {
-            return i;
-        }
-    }
-    limit
+pub fn escapes(c: Complex<f64>, limit: u64) -> u64 {
+    kernel::escapes(c, limit, false) as u64
 }
 
+/// Reference smooth count: `escapes` with the fractional part from
+/// [`smooth_count`](crate::renderer::smooth_count).
+#[inline]
+pub fn escapes_smooth(c: Complex<f64>, limit: u64) -> f32 {
+    let mut smooth = [0.0];
+    kernel::escapes_smooth(c, limit, false, &mut smooth);
+    smooth[0]
+}
+
+/// True for points of the main cardioid and the period-2 bulb, which never escape.
+#[inline]
+pub fn in_main_bulbs(c: Complex<f64>) -> bool {
+    kernel::in_main_bulbs(c)
+}
+
+/// `escapes` with the interior shortcuts of `kernel::iterate`. Same counts as `escapes`.
+#[inline]
+pub fn escapes_fast(c: Complex<f64>, limit: u64) -> u64 {
+    kernel::escapes(c, limit, true) as u64
+}
+
+/// `escapes_smooth` with the interior shortcuts of `kernel::iterate`.
+#[inline]
+pub fn escapes_smooth_fast(c: Complex<f64>, limit: u64) -> f32 {
+    let mut smooth = [0.0];
+    kernel::escapes_smooth(c, limit, true, &mut smooth);
+    smooth[0]
+}
@@ -0,0 +1,136 @@
+use crate::error::RenderError;
+use crate::registry::Precision;
+use crate::renderer::{MandelbrotRenderer, RenderParams, Count, Counts, check_tile};
+use crate::viewport::{Viewport, Tile};
+use std::collections::HashMap;
+
+/// Renders only one of every two rows of a tile that mirror each other across the real
+/// axis and copies it into the other one.
+///
+/// The set is symmetric under conjugation, and so is the iteration in IEEE arithmetic:
+/// conjugate points get bit for bit the same counts. Two rows are mirrors when the
+/// pixel map of the view gives them exactly conjugate points, which takes an unrotated
+/// view straddling `im = 0`; how many rows pair up depends on the rounding of the
+/// map. Backends that derive their points otherwise or fill in samples from their
+/// neighbours say so through [`MandelbrotRenderer::conjugate_symmetric`] and render
+/// every row, so the result is always that of a full render.
+pub struct Mirrored<R> {
+    inner: R,
+}
+
+impl<R: MandelbrotRenderer> Mirrored<R> {
+    pub fn wrap(inner: R) -> Mirrored<R> {
+        Mirrored { inner }
+    }
+
+    fn counts<C: Count>(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [C]) -> Result<(), RenderError> {
+        self.mirror(viewport, tile, out, |tile, out: &mut [C]| {
+            self.inner.render_tile(viewport, tile, params, C::counts(out))
+        })
+    }
+
+    /// Renders the rows of `tile` that have no mirror above them with `render`, in
+    /// runs of adjacent rows, then fills in the others.
+    fn mirror<T, F>(&self, viewport: &Viewport, tile: &Tile, out: &mut [T], mut render: F) -> Result<(), RenderError>
+        where T: Copy, F: FnMut(&Tile, &mut [T]) -> Result<(), RenderError>
+    {
+        check_tile(viewport, tile, out.len())?;
+        let sources = if self.inner.conjugate_symmetric(viewport) { mirror_rows(viewport, tile) } else { None };
+        let sources = match sources {
+            Some(sources) => sources,
+            None => return render(tile, out),
+        };
+
+        let width = tile.width;
+        let mut j = 0;
+        while j < tile.height {
+            if sources[j].is_some() {
+                j += 1;
+                continue;
+            }
+            let end = (j..tile.height).find(|&end| sources[end].is_some()).unwrap_or(tile.height);
+            let run = Tile { y: tile.pixel(0, j).1, height: end - j, ..*tile };
+            render(&run, &mut out[j * width..end * width])?;
+            j = end;
+        }
+        for (j, source) in sources.iter().enumerate() {
+            if let Some(source) = *source {
+                out.copy_within(source * width..(source + 1) * width, j * width);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// For every row of `tile`, the earlier row with exactly the conjugate points if there
+/// is one. `None` if no row has one.
+fn mirror_rows(viewport: &Viewport, tile: &Tile) -> Option<Vec<Option<usize>>> {
+    let map = viewport.pixel_map();
+    // only then re is the same in every row and im the same in every column
+    if map.dx.im != 0.0 || map.dy.re != 0.0 {
+        return None;
+    }
+    // rows by the bits of their im, zero without its sign
+    let mut rendered = HashMap::new();
+    let sources: Vec<_> = (0..tile.height)
+        .map(|j| {
+            let im = map.at(0.0, tile.pixel(0, j).1 as f64).im;
+            let source = rendered.get(&(0.0 - im).to_bits()).copied();
+            if source.is_none() {
+                rendered.entry((im + 0.0).to_bits()).or_insert(j);
+            }
+            source
+        })
+        .collect();
+    if sources.iter().any(Option::is_some) { Some(sources) } else { None }
+}
+
+impl<R: MandelbrotRenderer> MandelbrotRenderer for Mirrored<R> {
+    fn new() -> Result<Mirrored<R>, RenderError> {
+        R::new().map(Mirrored::wrap)
+    }
+    fn render_tile(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: Counts) -> Result<(), RenderError> {
+        match out {
+            Counts::U16(out) => self.counts(viewport, tile, params, out),
+            Counts::U32(out) => self.counts(viewport, tile, params, out),
+            Counts::F32(out) => self.counts(viewport, tile, params, out),
+        }
+    }
+    fn render_tile_smooth(&self, viewport: &Viewport, tile: &Tile, params: &RenderParams, out: &mut [f32]) -> Result<(), RenderError> {
+        self.mirror(viewport, tile, out, |tile, out| self.inner.render_tile_smooth(viewport, tile, params, out))
+    }
+    fn precision(&self, viewport: &Viewport, limit: usize) -> Option<Precision> {
+        self.inner.precision(viewport, limit)
+    }
+    fn conjugate_symmetric(&self, viewport: &Viewport) -> bool {
+        self.inner.conjugate_symmetric(viewport)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use num::Complex;
+
+    #[test]
+    fn pairs_rows_of_full_view() {
+        let viewport = Viewport::new(Complex::new(-0.5, 0.0), 3.0, (1024, 768));
+        let sources = mirror_rows(&viewport, &Tile::full(&viewport)).unwrap();
+        let map = viewport.pixel_map();
+        let im = |j: usize| map.at(0.0, j as f64).im;
+        for (j, source) in sources.iter().enumerate() {
+            if let Some(source) = *source {
+                assert!(source < j);
+                assert_eq!(im(source), -im(j));
+            }
+        }
+        // nearly half of the rows are copies
+        assert!(sources.iter().filter(|source| source.is_some()).count() > 300);
+
+        let mut rotated = viewport;
+        rotated.rotation = 0.1;
+        assert!(mirror_rows(&rotated, &Tile::full(&rotated)).is_none());
+        let above = Viewport::new(Complex::new(-0.5, 2.0), 3.0, (1024, 768));
+        assert!(mirror_rows(&above, &Tile::full(&above)).is_none());
+    }
+}
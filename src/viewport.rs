@@ -0,0 +1,177 @@
+use crate::fixed::Fixed;
+use num::Complex;
+
+/// The region of the complex plane shown in an image, and the pixel grid laid over it.
+///
+/// `scale` is the width of the view in the complex plane and `rotation` turns the view
+/// around `center`, in radians. Pixels are square: the height of the view follows from
+/// the aspect ratio of `width` and `height`. Pixel (0, 0) is the top-left corner, x grows
+/// to the right and y grows towards the bottom of the image.
+///
+/// `center` is `precise_center` rounded to f64. Deep zooms need the digits past f64, so
+/// move the view with `pan` or `set_center`, which keep the two in step.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+    pub center: Complex<f64>,
+    pub precise_center: Complex<Fixed>,
+    pub scale: f64,
+    pub rotation: f64,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// A block of samples of a viewport: `width` x `height` samples starting at pixel
+/// (`x`, `y`) and taken every `step` pixels. `step` is 1 for a plain rectangle.
+///
+/// Renderers return the samples of a tile row by row.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub step: usize,
+}
+
+impl Tile {
+    /// Every pixel of the viewport.
+    pub fn full(viewport: &Viewport) -> Tile {
+        Tile { x: 0, y: 0, width: viewport.width, height: viewport.height, step: 1 }
+    }
+
+    /// The samples of the grid with the given step and offset that fall inside the viewport.
+    pub fn grid(viewport: &Viewport, step: usize, x: usize, y: usize) -> Tile {
+        Tile {
+            x,
+            y,
+            width: (viewport.width.saturating_sub(x) + step - 1) / step,
+            height: (viewport.height.saturating_sub(y) + step - 1) / step,
+            step,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Viewport pixel of sample (i, j).
+    #[inline]
+    pub fn pixel(&self, i: usize, j: usize) -> (usize, usize) {
+        (self.x + i * self.step, self.y + j * self.step)
+    }
+}
+
+/// Affine pixel -> complex mapping of a [`Viewport`], precomputed for the inner loops.
+///
+/// Get one from [`Viewport::pixel_map`]; backends must not derive the mapping themselves.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PixelMap {
+    /// Complex coordinate of pixel (0, 0).
+    pub origin: Complex<f64>,
+    /// Step for one pixel to the right.
+    pub dx: Complex<f64>,
+    /// Step for one pixel down.
+    pub dy: Complex<f64>,
+}
+
+impl PixelMap {
+    #[inline]
+    pub fn at(&self, x: f64, y: f64) -> Complex<f64> {
+        Complex {
+            re: self.origin.re + x * self.dx.re + y * self.dy.re,
+            im: self.origin.im + x * self.dx.im + y * self.dy.im,
+        }
+    }
+}
+
+impl Viewport {
+    pub fn new(center: Complex<f64>, scale: f64, dims: (usize, usize)) -> Viewport {
+        Viewport {
+            center,
+            precise_center: Complex { re: Fixed::from_f64(center.re), im: Fixed::from_f64(center.im) },
+            scale,
+            rotation: 0.0,
+            width: dims.0,
+            height: dims.1,
+        }
+    }
+
+    pub fn dims(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Size of one pixel in the complex plane.
+    pub fn pixel_size(&self) -> f64 {
+        self.scale / self.width as f64
+    }
+
+    pub fn pixel_map(&self) -> PixelMap {
+        let step = self.pixel_size();
+        let (sin, cos) = self.rotation.sin_cos();
+        let dx = Complex { re: step * cos, im: step * sin };
+        let dy = Complex { re: -step * sin, im: step * cos };
+        let half_w = self.width as f64 / 2.0;
+        let half_h = self.height as f64 / 2.0;
+        let origin = Complex {
+            re: self.center.re - half_w * dx.re - half_h * dy.re,
+            im: self.center.im - half_w * dx.im - half_h * dy.im,
+        };
+        PixelMap { origin, dx, dy }
+    }
+
+    pub fn set_center(&mut self, center: Complex<Fixed>) {
+        self.precise_center = center;
+        self.center = Complex { re: center.re.to_f64(), im: center.im.to_f64() };
+    }
+
+    /// Moves the view by (dx, dy) pixels.
+    pub fn pan(&mut self, dx: f64, dy: f64) {
+        let PixelMap { dx: step_x, dy: step_y, .. } = self.pixel_map();
+        let re = dx * step_x.re + dy * step_y.re;
+        let im = dx * step_x.im + dy * step_y.im;
+        let center = self.precise_center;
+        self.set_center(Complex { re: center.re + Fixed::from_f64(re), im: center.im + Fixed::from_f64(im) });
+    }
+
+    /// Whole-pixel offset (dx, dy) such that `self` is `from` panned by (dx, dy), or
+    /// `None` if the pixel grids of the two do not line up.
+    pub fn pixel_offset(&self, from: &Viewport) -> Option<(isize, isize)> {
+        if self.dims() != from.dims() || self.scale != from.scale || self.rotation != from.rotation {
+            return None;
+        }
+        // the precise centers, as f64 cannot tell pixels apart in deep zooms
+        let d = Complex {
+            re: (self.precise_center.re - from.precise_center.re).to_f64(),
+            im: (self.precise_center.im - from.precise_center.im).to_f64(),
+        };
+        let PixelMap { dx: step_x, dy: step_y, .. } = from.pixel_map();
+        let step_sqr = step_x.norm_sqr();
+        let x = (d.re * step_x.re + d.im * step_x.im) / step_sqr;
+        let y = (d.re * step_y.re + d.im * step_y.im) / step_sqr;
+        let (dx, dy) = (x.round(), y.round());
+        // panning by whole pixels only leaves rounding noise
+        if (x - dx).abs() > 1e-6 || (y - dy).abs() > 1e-6 {
+            return None;
+        }
+        Some((dx as isize, dy as isize))
+    }
+
+    pub fn pixel_to_complex(&self, x: f64, y: f64) -> Complex<f64> {
+        self.pixel_map().at(x, y)
+    }
+
+    pub fn complex_to_pixel(&self, c: Complex<f64>) -> (f64, f64) {
+        let PixelMap { origin, dx, dy } = self.pixel_map();
+        let d = c - origin;
+        // dx and dy are orthogonal and of the same length, so projecting is enough
+        let step_sqr = dx.norm_sqr();
+        (
+            (d.re * dx.re + d.im * dx.im) / step_sqr,
+            (d.re * dy.re + d.im * dy.im) / step_sqr,
+        )
+    }
+}
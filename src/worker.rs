@@ -0,0 +1,180 @@
+use crate::error::RenderError;
+use crate::progressive::progressive;
+use crate::renderer::{MandelbrotRenderer, RenderParams, CancelToken, Counts};
+use crate::shift::shift;
+use crate::viewport::{Viewport, Tile};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+/// One frame to render on the worker thread.
+pub struct RenderJob {
+    pub renderer: Arc<dyn MandelbrotRenderer>,
+    pub viewport: Viewport,
+    pub limit: usize,
+    /// Smooth counts instead of integer ones, see `render_smooth`.
+    pub smooth: bool,
+}
+
+impl RenderJob {
+    /// Whole-pixel offset from `previous` if a finished frame of `previous` can be
+    /// shifted into the frame of this job.
+    fn offset_from(&self, previous: &RenderJob) -> Option<(isize, isize)> {
+        // compare the data pointers only, vtables of one type may be duplicated
+        let same_renderer = Arc::as_ptr(&self.renderer) as *const () == Arc::as_ptr(&previous.renderer) as *const ();
+        if !same_renderer || self.limit != previous.limit || self.smooth != previous.smooth {
+            return None;
+        }
+        self.viewport.pixel_offset(&previous.viewport)
+    }
+}
+
+pub enum RenderEvent {
+    /// A progressive pass of job `job`; the last one before `Done` is the finished frame.
+    Pass { job: u64, viewport: Viewport, values: Vec<f32> },
+    Done { job: u64, result: Result<(), RenderError> },
+}
+
+/// Renders jobs on a background thread and sends the frames back over a channel.
+///
+/// Submitting a job makes every earlier one stale: queued stale jobs are skipped and
+/// a stale job in flight is cancelled, finishing with `RenderError::Cancelled`.
+///
+/// The worker keeps the last frame it finished. A job that only pans it by whole
+/// pixels reuses it and renders just the exposed strips, in a single pass.
+pub struct RenderWorker {
+    jobs: Sender<(u64, RenderJob, CancelToken)>,
+    events: Receiver<RenderEvent>,
+    latest: Arc<AtomicU64>,
+    /// Token of the last submitted job.
+    cancel: CancelToken,
+}
+
+impl RenderWorker {
+    pub fn spawn() -> RenderWorker {
+        let (jobs, job_rx) = channel::<(u64, RenderJob, CancelToken)>();
+        let (event_tx, events) = channel();
+        let latest = Arc::new(AtomicU64::new(0));
+        let worker_latest = latest.clone();
+        thread::spawn(move || {
+            let mut last: Option<(RenderJob, Vec<f32>)> = None;
+            while let Ok(mut next) = job_rx.recv() {
+                // only the newest of the queued jobs is worth rendering
+                while let Ok(newer) = job_rx.try_recv() {
+                    next = newer;
+                }
+                let (id, job, cancel) = next;
+                let result = run(id, &job, last.as_ref(), &cancel, &worker_latest, &event_tx)
+                    .map(|values| last = Some((job, values)));
+                if event_tx.send(RenderEvent::Done { job: id, result }).is_err() {
+                    break;
+                }
+            }
+        });
+        RenderWorker { jobs, events, latest, cancel: CancelToken::new() }
+    }
+
+    /// Queues `job`, cancels the one before it and returns the id of `job`.
+    /// `RenderError::WorkerStopped` if a render panicked and took the worker thread down.
+    pub fn submit(&mut self, job: RenderJob) -> Result<u64, RenderError> {
+        let id = self.latest.fetch_add(1, Ordering::SeqCst) + 1;
+        self.cancel.cancel();
+        self.cancel = CancelToken::new();
+        self.jobs.send((id, job, self.cancel.clone())).map_err(|_| RenderError::WorkerStopped)?;
+        Ok(id)
+    }
+
+    pub fn try_recv(&self) -> Option<RenderEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+/// Renders `job` and returns its finished frame.
+fn run(id: u64, job: &RenderJob, last: Option<&(RenderJob, Vec<f32>)>, cancel: &CancelToken,
+       latest: &AtomicU64, events: &Sender<RenderEvent>) -> Result<Vec<f32>, RenderError> {
+    let viewport = job.viewport;
+    let params = RenderParams { limit: job.limit, cancel: cancel.clone() };
+    let send = |values: &[f32]| {
+        if latest.load(Ordering::SeqCst) == id {
+            let _ = events.send(RenderEvent::Pass { job: id, viewport, values: values.to_vec() });
+        }
+    };
+    // counts go straight into f32 buffers, which is what the viewer colors
+    let render_tile = |tile: &Tile, out: &mut [f32]| if job.smooth {
+        job.renderer.render_tile_smooth(&viewport, tile, &params, out)
+    } else {
+        job.renderer.render_tile(&viewport, tile, &params, Counts::F32(out))
+    };
+
+    if let Some((previous, frame)) = last {
+        if let Some(offset) = job.offset_from(previous) {
+            let values = shift(&viewport, frame, offset, render_tile)?;
+            send(&values);
+            return Ok(values);
+        }
+    }
+    progressive(&viewport, render_tile, send)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SingleMandelbrot;
+    use num::Complex;
+
+    /// Passes of job `id` until it is done, panics on any error of it.
+    fn passes(worker: &RenderWorker, id: u64) -> Vec<Vec<f32>> {
+        let mut passes = Vec::new();
+        loop {
+            match worker.events.recv().unwrap() {
+                RenderEvent::Pass { job, values, .. } if job == id => passes.push(values),
+                RenderEvent::Done { job, result } if job == id => {
+                    result.unwrap();
+                    return passes;
+                }
+                // superseded before it finished, or skipped entirely
+                RenderEvent::Done { result, .. } => {
+                    assert!(matches!(result, Ok(()) | Err(RenderError::Cancelled)));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn counts(renderer: &Arc<dyn MandelbrotRenderer>, viewport: &Viewport, limit: usize) -> Vec<f32> {
+        renderer.render(viewport, limit).unwrap().into_iter().map(|c| c as f32).collect()
+    }
+
+    #[test]
+    fn finishes_latest_job() {
+        let mut worker = RenderWorker::spawn();
+        let renderer: Arc<dyn MandelbrotRenderer> = Arc::new(SingleMandelbrot::new().unwrap());
+        let viewport = Viewport::new(Complex::new(-0.5, 0.0), 3.0, (16, 8));
+        let job = |limit| RenderJob { renderer: renderer.clone(), viewport, limit, smooth: false };
+        worker.submit(job(10)).unwrap();
+        let last = worker.submit(job(20)).unwrap();
+
+        assert_eq!(passes(&worker, last).pop(), Some(counts(&renderer, &viewport, 20)));
+    }
+
+    #[test]
+    fn reuses_panned_frame() {
+        let mut worker = RenderWorker::spawn();
+        let renderer: Arc<dyn MandelbrotRenderer> = Arc::new(SingleMandelbrot::new().unwrap());
+        // pixels of 1/16 keep every coordinate exact, so reused pixels match a fresh render
+        let viewport = Viewport::new(Complex::new(-0.5, 0.0), 4.0, (64, 16));
+        let job = |viewport| RenderJob { renderer: renderer.clone(), viewport, limit: 50, smooth: false };
+        let first = worker.submit(job(viewport)).unwrap();
+        passes(&worker, first);
+
+        for &(dx, dy) in &[(8.0, 3.0), (-5.0, 0.0), (0.0, -16.0), (100.0, 2.0)] {
+            let mut panned = viewport;
+            panned.pan(dx, dy);
+            let id = worker.submit(job(panned)).unwrap();
+            let passes = passes(&worker, id);
+            assert_eq!(passes.len(), 1, "({}, {})", dx, dy);
+            assert_eq!(passes[0], counts(&renderer, &panned, 50), "({}, {})", dx, dy);
+        }
+    }
+}